@@ -0,0 +1,139 @@
+//! ### What the installation reports while it runs, for front-ends.
+//! Events are sent to a single listener, set with [`set_listener`] or [`Installer::on_progress`](crate::Installer::on_progress).
+//! They serialize to JSON with an `event` tag, see [`to_json`].
+
+use crate::installer::Step;
+use lazy_static::lazy_static;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// The version of the JSON event schema, bumped when an event changes in an incompatible way.
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum Event {
+    /// `index` counts from 1 up to `total`, the number of steps the plan enables
+    StepStarted {
+        step: Step,
+        index: usize,
+        total: usize,
+        /// Overall progress of the installation, 0 to 100
+        percent: u8,
+    },
+    StepFinished {
+        step: Step,
+        index: usize,
+        total: usize,
+        percent: u8,
+    },
+    StepFailed {
+        step: Step,
+        error: String,
+    },
+    /// A package transaction moved on, `current` counts from 1 up to `total`
+    Packages {
+        phase: PackagePhase,
+        current: u64,
+        total: u64,
+        name: String,
+    },
+    Warning {
+        message: String,
+    },
+    /// ### A question asked during a package transaction, answered the way `pacman --noconfirm` would.
+    /// It is informational only, the answer is already given and front-ends cannot change it.
+    Prompt {
+        question: String,
+        answer: String,
+    },
+    /// The command finished, always the last event
+    Finished,
+    /// The command failed, always the last event
+    Failed {
+        error: String,
+        exit_code: i32,
+    },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PackagePhase {
+    Download,
+    Install,
+    Hook,
+}
+
+/// An event as written on a line, with the schema version.
+#[derive(Serialize)]
+struct Line<'a> {
+    version: u32,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// One line of JSON, e.g. `{"version":1,"event":"warning","message":"..."}`.
+pub fn to_json(event: &Event) -> String {
+    serde_json::to_string(&Line {
+        version: SCHEMA_VERSION,
+        event,
+    })
+    .unwrap()
+}
+
+/// The event as a JSON object, with the schema version.
+pub fn to_value(event: &Event) -> serde_json::Value {
+    serde_json::to_value(Line {
+        version: SCHEMA_VERSION,
+        event,
+    })
+    .unwrap()
+}
+
+type Listener = Box<dyn FnMut(&Event) + Send>;
+
+lazy_static! {
+    static ref LISTENER: Mutex<Option<Listener>> = Mutex::new(None);
+}
+
+static STDOUT_RESERVED: AtomicBool = AtomicBool::new(false);
+
+/// ### Sends every event to `listener`, replacing the previous one.
+/// It is called while the listener is locked, so it must not emit events itself,
+/// e.g. by logging a warning when warnings are forwarded as events.
+pub fn set_listener<F: FnMut(&Event) + Send + 'static>(listener: F) {
+    *LISTENER.lock().unwrap() = Some(Box::new(listener));
+}
+
+pub fn emit(event: Event) {
+    if let Some(listener) = LISTENER.lock().unwrap().as_mut() {
+        listener(&event);
+    }
+}
+
+/// Like [`emit`], but gives up if the listener is busy or poisoned, e.g. for a panic inside the listener.
+pub fn try_emit(event: Event) {
+    if let Ok(mut listener) = LISTENER.try_lock() {
+        if let Some(listener) = listener.as_mut() {
+            listener(&event);
+        }
+    }
+}
+
+/// Keeps stdout for the events, commands and the dry run then print to stderr instead.
+pub fn reserve_stdout() {
+    STDOUT_RESERVED.store(true, Ordering::Relaxed);
+}
+
+pub fn stdout_reserved() -> bool {
+    STDOUT_RESERVED.load(Ordering::Relaxed)
+}
+
+/// The overall progress once `done` of `total` steps are finished.
+pub fn percent(done: usize, total: usize) -> u8 {
+    if total == 0 {
+        return 100;
+    }
+    (done * 100 / total) as u8
+}
@@ -0,0 +1,577 @@
+use crate::types::{GraphicsMode, Microcode};
+use crate::functions::hardware::{self, Firmware};
+use crate::functions::{initramfs, pacman};
+use crate::internal::exec::*;
+use crate::internal::*;
+use crate::internal::manifest;
+use log::warn;
+use std::path::PathBuf;
+
+/// ### Installs the base packages for the system to proprely work.
+/// This functions takes the kernels to install as an argument, each one gets its headers.
+/// The CPU microcode is detected unless overridden by `microcode`.
+/// - eg: `install_base_packages(vec![String::from("linux-zen"), String::from("linux-lts")], Microcode::Auto)`.
+pub fn install_base_packages(kernels: Vec<String>, microcode: Microcode) -> Result<()> {
+    files_eval(files::create_directory("/mnt/etc"), "Create /mnt/etc")?;
+    install::install(base_packages(kernels, microcode)?, true)?;
+    offline::copy_pacman_conf()?;
+    initramfs::require(initramfs::Feature::Plymouth);
+    initramfs::save()?;
+
+    let hypervisor = hardware::detect_hypervisor();
+    if hypervisor.is_virtual() {
+        // Some guest units are only socket or udev activated, don't fail on them
+        for service in hypervisor.guest_tools().1 {
+            soft_exec_eval(
+                exec_chroot("systemctl", vec![String::from("enable"), service.clone()]),
+                format!("Enable {}", service).as_str(),
+            );
+        }
+    }
+    install::enable_services(manifest::select("base").services)
+}
+
+/// ### Returns the base packages without installing them.
+/// That is the base set from the manifests, the keyrings of the enabled repositories, the kernels with their headers, the graphic
+/// drivers, the guest tools when running in a virtual machine and the CPU microcode.
+/// Fails if a kernel is unknown, see [`resolve_kernels`].
+pub fn base_packages(kernels: Vec<String>, microcode: Microcode) -> Result<Vec<String>> {
+    let mut packages = manifest::select("base").packages;
+    packages.extend(pacman::repository_packages());
+    for kernel in resolve_kernels(kernels)? {
+        let headers = format!("{kernel}-headers");
+        if preflight::in_sync_db(&headers) {
+            packages.push(kernel);
+            packages.push(headers);
+        } else {
+            warn!("No headers found for kernel {}, installing it without them", kernel);
+            packages.push(kernel);
+        }
+    }
+    // Graphic drivers, NVIDIA GPUs get nouveau until `install_nvidia` runs
+    packages.extend(hardware::gpu_packages(&hardware::detect_gpus(), false));
+    let hypervisor = hardware::detect_hypervisor();
+    if hypervisor.is_virtual() {
+        log::info!("Running in a virtual machine ({:?}), skipping physical-only packages", hypervisor);
+        packages.extend(hypervisor.guest_tools().0);
+    }
+    if let Some(ucode) = hardware::microcode_package(microcode) {
+        log::info!("Installing microcode {}", ucode);
+        packages.push(String::from(ucode));
+    }
+    Ok(packages)
+}
+
+/// Kernels packaged for Arch, offered when an unknown kernel is asked for.
+const KERNELS: &[&str] = &[
+    "linux",
+    "linux-lts",
+    "linux-zen",
+    "linux-hardened",
+    "linux-rt",
+    "linux-rt-lts",
+];
+
+/// ### Validates the requested kernels against the pre-flight sync databases, synchronized first if needed.
+/// Kernels of extra and offline repositories are found there too, see [`preflight::in_sync_db`].
+/// An unknown kernel is an error listing the available ones, `linux` is used if none is requested.
+fn resolve_kernels(kernels: Vec<String>) -> Result<Vec<String>> {
+    preflight::ensure_synced()?;
+    let mut resolved: Vec<String> = Vec::new();
+    for kernel in kernels.iter().filter(|k| !k.is_empty()) {
+        if resolved.contains(kernel) {
+            continue;
+        }
+        if !preflight::in_sync_db(kernel) {
+            let valid: Vec<&str> = KERNELS
+                .iter()
+                .copied()
+                .filter(|k| preflight::in_sync_db(k))
+                .collect();
+            return Err(InstallError::invalid(format!(
+                "Unknown kernel {}, valid kernels are: {}",
+                kernel,
+                valid.join(", ")
+            )));
+        }
+        resolved.push(kernel.clone());
+    }
+    if resolved.is_empty() {
+        log::info!("No kernel selected, using linux");
+        resolved.push(String::from("linux"));
+    }
+    Ok(resolved)
+}
+
+/// ### This will setup the archlinux keyring using the `pacman-key` command.
+/// It will first initialize it with the `--init` flag, then populate it with the `--populate` flag.
+/// The signing keys of extra repositories from the pacman settings are imported last.
+pub fn setup_archlinux_keyring() -> Result<()> {
+    exec_eval(
+        exec_chroot("pacman-key", vec![String::from("--init")]),
+        "Initialize pacman keyring",
+    )?;
+    exec_eval(
+        exec_chroot(
+            "pacman-key",
+            vec![String::from("--populate")],
+        ),
+        "Populate pacman keyring",
+    )?;
+    pacman::import_repository_keys()
+}
+
+/// Returns the Flatpak packages without installing them.
+pub fn flatpak_packages() -> Vec<String> {
+    manifest::select("flatpak").packages
+}
+
+/// ### Function to add the Flathub remote for Flatpak.
+/// This is not available in axinstall GUI because I don't like flatpak
+pub fn install_flatpak() -> Result<()> {
+    install::install_set("flatpak", false)?;
+    exec_eval(
+        exec_chroot(
+            "flatpak",
+            vec![
+                String::from("remote-add"),
+                String::from("--if-not-exists"),
+                String::from("flathub"),
+                String::from("https://dl.flathub.org/repo/flathub.flatpakrepo"),
+            ],
+        ),
+        "Add Flathub remote",
+    )
+}
+
+/// Generates the file system tab in `/mnt/etc/fstab` using the `genfstab` command.
+pub fn genfstab() -> Result<()> {
+    let fstab = match Cmd::new("genfstab").args(["-U", "/mnt"]).output() {
+        Ok(output) if output.status.success() => output.stdout,
+        Ok(output) => {
+            return Err(InstallError::Command {
+                context: String::from("Generate fstab"),
+                code: output.status.code(),
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            })
+        }
+        Err(e) => return Err(InstallError::io("Generate fstab", e)),
+    };
+    files_eval(
+        files::append_file("/mnt/etc/fstab", &String::from_utf8_lossy(&fstab)),
+        "Generate fstab",
+    )
+}
+
+/// ### Install GRUB
+/// This will first install the patched grub from the AxOS repo, then install efibootmgr and os-prober.
+/// 
+/// Then, it will use `grub-install` to install the grub in the efidir, provided as an arg to this function.
+/// Every installed kernel gets its own top level entry, `default_kernel` picks the one booted by default.
+pub fn install_bootloader_efi(efidir: PathBuf, default_kernel: Option<String>) -> Result<()> {
+    install_bootloader_set(Firmware::Uefi)?;
+    let efidir = std::path::Path::new("/mnt").join(efidir);
+    let efi_str = efidir.to_str().unwrap();
+    if !runner::simulated() && !std::path::Path::new(&format!("/mnt{efi_str}")).exists() {
+        return Err(InstallError::invalid(format!("The efidir {efidir:?} doesn't exist")));
+    }
+    exec_eval(
+        exec_chroot(
+            "grub-install",
+            vec![
+                String::from("--target=x86_64-efi"),
+                format!("--efi-directory={}", efi_str),
+                String::from("--bootloader-id=axos"),
+                String::from("--removable"),
+            ],
+        ),
+        "install grub as efi with --removable",
+    )?;
+    exec_eval(
+        exec_chroot(
+            "grub-install",
+            vec![
+                String::from("--target=x86_64-efi"),
+                format!("--efi-directory={}", efi_str),
+                String::from("--bootloader-id=axos"),
+            ],
+        ),
+        "install grub as efi without --removable",
+    )?;
+    enable_apparmor_lsm()?;
+    configure_grub_kernels(default_kernel)?;
+    log_microcode();
+    exec_eval(
+        exec_chroot(
+            "grub-mkconfig",
+            vec![String::from("-o"), String::from("/boot/grub/grub.cfg")],
+        ),
+        "create grub.cfg",
+    )
+}
+
+/// Sets the LSM kernel parameter enabling AppArmor, only on the `GRUB_CMDLINE_LINUX_DEFAULT` line.
+/// An existing `lsm=` parameter is replaced.
+pub(crate) fn enable_apparmor_lsm() -> Result<()> {
+    let lsm_param = "lsm=landlock,lockdown,yama,integrity,apparmor,bpf";
+    exec_eval(
+        exec_chroot(
+            "sed",
+            vec![
+                String::from("-i"),
+                format!(
+                    r#"/^\s*GRUB_CMDLINE_LINUX_DEFAULT=/ {{ s/lsm=[^ "']*//g; s/"$/ {lsm_param}"/ }}"#
+                ),
+                String::from("/etc/default/grub"),
+            ],
+        ),
+        "append AppArmor LSM param to GRUB config",
+    )
+}
+
+/// Installs the bootloader set for `firmware`, whatever the live system was booted with.
+fn install_bootloader_set(firmware: Firmware) -> Result<()> {
+    install::install_selection(bootloader_selection(firmware), true)
+}
+
+fn bootloader_selection(firmware: Firmware) -> manifest::Selection {
+    let mut facts = manifest::facts();
+    facts.firmware = firmware;
+    manifest::select_with("bootloader", &facts)
+}
+
+/// Returns the bootloader packages for `firmware` without installing them.
+pub fn bootloader_packages(firmware: Firmware) -> Vec<String> {
+    bootloader_selection(firmware).packages
+}
+
+/// Lists a top level GRUB entry for every kernel and makes `default_kernel` the default one.
+fn configure_grub_kernels(default_kernel: Option<String>) -> Result<()> {
+    set_grub_option("GRUB_DISABLE_SUBMENU", "y")?;
+    if let Some(kernel) = default_kernel {
+        let image = format!("/boot/vmlinuz-{kernel}");
+        if std::path::Path::new(&format!("/mnt{image}")).exists() {
+            log::info!("Using {} as default boot entry", kernel);
+            set_grub_option("GRUB_TOP_LEVEL", &format!("\"{image}\""))?;
+        } else {
+            warn!("Default kernel {} is not installed, keeping GRUB's default", kernel);
+        }
+    }
+    Ok(())
+}
+
+/// GRUB picks up `/boot/*-ucode.img` on its own when generating its config,
+/// so we only report what it is going to load.
+fn log_microcode() {
+    for ucode in ["intel-ucode.img", "amd-ucode.img"] {
+        if std::path::Path::new("/mnt/boot").join(ucode).exists() {
+            log::info!("GRUB will load microcode /boot/{}", ucode);
+        }
+    }
+}
+
+/// Sets `key=value` in `/mnt/etc/default/grub`, replacing any existing (even commented) assignment.
+fn set_grub_option(key: &str, value: &str) -> Result<()> {
+    let content = std::fs::read_to_string("/mnt/etc/default/grub").unwrap_or_default();
+    let mut found = false;
+    let mut lines: Vec<String> = content
+        .lines()
+        .map(|line| {
+            let uncommented = line.trim_start().trim_start_matches('#').trim_start();
+            if !found && uncommented.starts_with(&format!("{key}=")) {
+                found = true;
+                format!("{key}={value}")
+            } else {
+                line.to_string()
+            }
+        })
+        .collect();
+    if !found {
+        lines.push(format!("{key}={value}"));
+    }
+    files_eval(
+        files::write_file("/mnt/etc/default/grub", &(lines.join("\n") + "\n")),
+        format!("Set {key} in GRUB config").as_str(),
+    )
+}
+
+/// Is here but never used as AxOS doesn't support legacy BIOS.
+pub fn install_bootloader_legacy(device: PathBuf) -> Result<()> {
+    install_bootloader_set(Firmware::Bios)?;
+    if !device.exists() {
+        return Err(InstallError::MissingDevice(device));
+    }
+    let device = device.to_string_lossy().to_string();
+    exec_eval(
+        exec_chroot(
+            "grub-install",
+            vec![String::from("--target=i386-pc"), device],
+        ),
+        "install grub as legacy",
+    )?;
+    log_microcode();
+    exec_eval(
+        exec_chroot(
+            "grub-mkconfig",
+            vec![String::from("-o"), String::from("/boot/grub/grub.cfg")],
+        ),
+        "create grub.cfg",
+    )
+}
+
+/// Copy some files from the live to the installation
+pub fn copy_live_config() -> Result<()> {
+    offline::copy_pacman_conf()?;
+    files::copy_file("/etc/axos-version", "/mnt/etc/axos-version")?;
+    files_eval(
+        files::create_directory("/mnt/etc/sddm.conf.d"),
+        "Create /mnt/etc/sddm.conf.d",
+    )?;
+    files::copy_file(
+        "/etc/sddm.conf.d/settings.conf",
+        "/mnt/etc/sddm.conf.d/settings.conf",
+    )?;
+    files::copy_file("/etc/sddm.conf", "/mnt/etc/sddm.conf")?;
+    // files::copy_file("/etc/mkinitcpio.conf", "/mnt/etc/mkinitcpio.conf"); // Why is this even there ???
+    Ok(())
+}
+
+/// ### Installs the NVIDIA driver stack matching the detected GPU.
+/// Turing and newer get the open kernel modules, Maxwell to Volta the proprietary ones
+/// and Kepler the 470xx legacy branch. nouveau is blacklisted, the grub config is patched
+/// and the nvidia modules are required in the initramfs.
+///
+/// On hybrid (Optimus) laptops, `mode` picks between the integrated GPU only,
+/// PRIME render offload, or the NVIDIA GPU only.
+pub fn install_nvidia(mode: GraphicsMode) -> Result<()> {
+    let gpus = hardware::detect_gpus();
+    let hybrid = hardware::is_hybrid(&gpus);
+    if hybrid && mode == GraphicsMode::Integrated {
+        log::info!("Hybrid graphics in integrated mode, powering off the NVIDIA GPU");
+        disable_nvidia_gpu()?;
+        return Ok(());
+    }
+    install(nvidia_packages(mode), true)?;
+    files_eval(
+        files::write_file(
+            "/mnt/etc/modprobe.d/blacklist-nouveau.conf",
+            "blacklist nouveau\noptions nouveau modeset=0\n",
+        ),
+        "Blacklist nouveau",
+    )?;
+    setup_nvidia_power_management(hybrid)?;
+    if hybrid && mode == GraphicsMode::Dedicated {
+        files_eval(
+            files::write_file(
+                "/mnt/etc/X11/xorg.conf.d/10-nvidia-primary.conf",
+                "Section \"OutputClass\"\n\
+                 Identifier \"nvidia\"\n\
+                 MatchDriver \"nvidia-drm\"\n\
+                 Driver \"nvidia\"\n\
+                 Option \"PrimaryGPU\" \"yes\"\n\
+                 EndSection\n",
+            ),
+            "Make the NVIDIA GPU the primary X11 GPU",
+        )?;
+    }
+
+    // Apply nvidia module in grub
+    let grub_cmdline_content = std::fs::read_to_string("/mnt/etc/default/grub").unwrap_or_default();
+    let mut grub_conf_found = false;
+    let mut lines: Vec<String> = grub_cmdline_content.lines().map(|line| {
+        if line.starts_with("GRUB_CMDLINE_LINUX_DEFAULT=") {
+            grub_conf_found = true;
+            if line.contains("nvidia-drm.modeset=1") {
+                line.to_string() // Already there, do nothing
+            } else {
+                line.replace("GRUB_CMDLINE_LINUX_DEFAULT=\"", "GRUB_CMDLINE_LINUX_DEFAULT=\"nvidia-drm.modeset=1 ")
+            }
+        } else { line.to_string() }
+    }).collect();
+    if !grub_conf_found { lines.push("GRUB_CMDLINE_LINUX_DEFAULT=\"nvidia-drm.modeset=1\"".to_string()); }
+    let new_grub_content = lines.join("\n");
+    files_eval(
+        files::write_file("/mnt/etc/default/grub", &new_grub_content),
+        "Enable nvidia-drm modeset in GRUB config",
+    )?;
+
+    // Load the nvidia modules early, the initramfs is regenerated at the end of the install
+    for module in ["nvidia", "nvidia_modeset", "nvidia_uvm", "nvidia_drm"] {
+        initramfs::require_module(module);
+    }
+    initramfs::save()
+}
+
+/// ### Returns the NVIDIA packages for the detected GPU without installing them.
+/// Nothing is needed on hybrid laptops running on the integrated GPU only.
+pub fn nvidia_packages(mode: GraphicsMode) -> Vec<String> {
+    let gpus = hardware::detect_gpus();
+    let hybrid = hardware::is_hybrid(&gpus);
+    if hybrid && mode == GraphicsMode::Integrated {
+        return Vec::new();
+    }
+    let driver = gpus
+        .into_iter()
+        .map(|gpu| gpu.driver_stack)
+        .find(|driver| driver.is_nvidia())
+        .unwrap_or_else(|| {
+            warn!("No supported NVIDIA GPU detected, installing the open kernel modules");
+            hardware::GpuDriver::NvidiaOpen
+        });
+    log::info!("NVIDIA driver stack : {:?}", driver);
+    let mut facts = manifest::facts();
+    facts.gpus = vec![driver];
+    let mut packages = manifest::select_with("nvidia", &facts).packages;
+    if hybrid {
+        packages.push(String::from("nvidia-prime"));
+    }
+    packages
+}
+
+/// Keeps the video memory across suspend and enables the matching systemd units.
+/// On hybrid laptops the NVIDIA GPU is also allowed to power down when idle, `prime-run`
+/// from nvidia-prime offloads applications to it.
+fn setup_nvidia_power_management(hybrid: bool) -> Result<()> {
+    let mut options = String::from("options nvidia NVreg_PreserveVideoMemoryAllocations=1\n");
+    if hybrid {
+        options.push_str("options nvidia NVreg_DynamicPowerManagement=0x02\n");
+    }
+    files_eval(
+        files::write_file("/mnt/etc/modprobe.d/nvidia-power.conf", &options),
+        "Write NVIDIA power management options",
+    )?;
+    for service in ["nvidia-suspend", "nvidia-resume", "nvidia-hibernate"] {
+        exec_eval(
+            exec_chroot("systemctl", vec![String::from("enable"), String::from(service)]),
+            format!("Enable {}", service).as_str(),
+        )?;
+    }
+    Ok(())
+}
+
+/// Leaves the NVIDIA GPU of a hybrid laptop without a driver and removes it from the
+/// PCI bus at boot, so that it stays powered off.
+fn disable_nvidia_gpu() -> Result<()> {
+    files_eval(
+        files::write_file(
+            "/mnt/etc/modprobe.d/blacklist-nvidia.conf",
+            "blacklist nouveau\nblacklist nvidia\nblacklist nvidia_drm\nblacklist nvidia_modeset\nblacklist nvidia_uvm\n",
+        ),
+        "Blacklist NVIDIA modules",
+    )?;
+    files_eval(
+        files::write_file(
+            "/mnt/etc/udev/rules.d/00-remove-nvidia.rules",
+            "ACTION==\"add\", SUBSYSTEM==\"pci\", ATTR{vendor}==\"0x10de\", ATTR{class}==\"0x03[0-9]*\", ATTR{power/control}=\"auto\", ATTR{remove}=\"1\"\n",
+        ),
+        "Remove the NVIDIA GPU from the PCI bus at boot",
+    )
+}
+
+/// Will enable swap (self explainatory)
+pub fn enable_swap(size: u64) -> Result<()> {
+    let size_mb = size.to_string();
+    exec_eval(
+        exec(
+            "fallocate",
+            vec![
+                String::from("-l"),
+                format!("{}M", size_mb),
+                String::from("/mnt/swapfile"),
+            ],
+        ),
+        "Create swapfile",
+    )?;
+    exec_eval(
+        exec(
+            "chmod",
+            vec![String::from("600"), String::from("/mnt/swapfile")],
+        ),
+        "Set swapfile permissions",
+    )?;
+    exec_eval(
+        exec("mkswap", vec![String::from("/mnt/swapfile")]),
+        "Format swapfile",
+    )?;
+    files_eval(
+        files::write_file("/mnt/etc/fstab", "\n/swapfile none swap defaults 0 0\n"),
+        "Add swapfile to fstab",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::functions::pacman::{PacmanSettings, Repository};
+    use crate::internal::runner::{self, tests::chroot, tests::record};
+
+    /// A `pacman` knowing `linux` from any `pacman.conf`, and `linux-cachyos` only
+    /// from one with the `[cachyos]` repository.
+    const FAKE_PACMAN: &str = r#"#!/bin/sh
+conf=/etc/pacman.conf
+for arg; do
+    [ "$prev" = --config ] && conf=$arg
+    prev=$arg
+done
+case $prev in
+    -Sy | linux | linux-headers) exit 0 ;;
+    linux-cachyos | linux-cachyos-headers) grep -qx '\[cachyos\]' "$conf" ;;
+    *) exit 1 ;;
+esac
+"#;
+
+    fn install_fake_pacman() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir =
+            std::env::temp_dir().join(format!("axinstall-fake-pacman-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let pacman = dir.join("pacman");
+        std::fs::write(&pacman, FAKE_PACMAN).unwrap();
+        std::fs::set_permissions(&pacman, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let path = std::env::var("PATH").unwrap_or_default();
+        std::env::set_var("PATH", format!("{}:{}", dir.display(), path));
+    }
+
+    #[test]
+    fn lsm_param_only_touches_the_default_cmdline() {
+        let recorder = record(|| enable_apparmor_lsm().unwrap());
+        recorder.assert_operations(&[chroot(
+            &[
+                "sed",
+                "-i",
+                r#"/^\s*GRUB_CMDLINE_LINUX_DEFAULT=/ { s/lsm=[^ "']*//g; s/"$/ lsm=landlock,lockdown,yama,integrity,apparmor,bpf"/ }"#,
+                "/etc/default/grub",
+            ],
+            None,
+        )]);
+    }
+
+    #[test]
+    fn kernels_of_extra_repositories_are_valid() {
+        runner::tests::perform_for_real(|| {
+            install_fake_pacman();
+            let cachyos = vec![String::from("linux-cachyos")];
+            assert_eq!(
+                resolve_kernels(cachyos.clone()).unwrap_err().to_string(),
+                "Unknown kernel linux-cachyos, valid kernels are: linux"
+            );
+
+            pacman::configure(PacmanSettings {
+                chaotic_aur: false,
+                repositories: vec![Repository {
+                    name: String::from("cachyos"),
+                    server: String::from("https://mirror.cachyos.org/repo/$arch/$repo"),
+                    sig_level: None,
+                    key: None,
+                    key_file: None,
+                }],
+                ..PacmanSettings::default()
+            });
+            pacman::write_install_conf().unwrap();
+            preflight::sync_databases().unwrap();
+            let resolved = resolve_kernels(cachyos);
+            pacman::unconfigure();
+            assert_eq!(resolved.unwrap(), ["linux-cachyos"]);
+        });
+    }
+}
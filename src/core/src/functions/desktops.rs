@@ -0,0 +1,107 @@
+
+use crate::types::DesktopSetup;
+use crate::internal::exec::*;
+use crate::internal::*;
+use crate::internal::manifest;
+
+/// Will install the provided desktop on the installation
+pub fn install_desktop_setup(desktop_setup: DesktopSetup) -> Result<()> {
+    log::debug!("Installing {:?}", desktop_setup);
+    match desktop_setup {
+        DesktopSetup::Kde => install_kde()?,
+        DesktopSetup::Calla => install_calla()?,
+        DesktopSetup::Sleex => install_sleex()?,
+        DesktopSetup::Theom => install_theom()?,
+        DesktopSetup::None => log::debug!("No desktop setup selected"),
+    }
+    install_networkmanager()
+}
+
+/// Returns the packages of a desktop setup, network manager included, without installing them.
+pub fn desktop_packages(desktop_setup: DesktopSetup) -> Vec<String> {
+    let mut packages = match desktop_set(desktop_setup) {
+        Some(set) => manifest::select(set).packages,
+        None => Vec::new(),
+    };
+    packages.extend(manifest::select("networkmanager").packages);
+    packages
+}
+
+fn desktop_set(desktop_setup: DesktopSetup) -> Option<&'static str> {
+    match desktop_setup {
+        DesktopSetup::Kde => Some("desktop-kde"),
+        DesktopSetup::Calla => Some("desktop-calla"),
+        DesktopSetup::Sleex => Some("desktop-sleex"),
+        DesktopSetup::Theom => Some("desktop-theom"),
+        DesktopSetup::None => None,
+    }
+}
+
+fn install_networkmanager() -> Result<()> {
+    install::install_set("networkmanager", true)
+}
+
+fn install_calla() -> Result<()> {
+    install::install_set("desktop-calla", true)
+}
+
+fn install_kde() -> Result<()> {
+    install::install_set("desktop-kde", true)
+}
+
+fn install_sleex() -> Result<()> {
+    install::install_set("desktop-sleex", true)?;
+    set_sddm_sleex_default()
+}
+
+fn install_theom() -> Result<()> {
+    install::install_set("desktop-theom", true)?;
+    set_sddm_theom_default();
+    Ok(())
+}
+
+fn set_sddm_sleex_default() -> Result<()> {
+    exec_eval(
+        exec_chroot(
+            "mv",
+            vec![
+                String::from("/usr/share/wayland-sessions/hyprland.desktop"),
+                String::from("/usr/share/wayland-sessions/hyprland.desktop.hidden"),
+            ],
+        ),
+        "Rename hyprland.desktop to hyprland.desktop.hidden",
+    )?;
+    exec_eval(
+        exec_chroot(
+            "mv",
+            vec![
+                String::from("/usr/share/wayland-sessions/hyprland-uwsm.desktop"),
+                String::from("/usr/share/wayland-sessions/hyprland-uwsm.desktop.hidden"),
+            ],
+        ),
+        "Rename hyprland-uwsm.desktop to hyprland-uwsm.desktop.hidden",
+    )
+}
+
+fn set_sddm_theom_default() {
+    soft_exec_eval(
+        exec_chroot(
+            "mv",
+            vec![
+                String::from("/usr/share/xsessions/i3.desktop"),
+                String::from("/usr/share/xsessions/i3.desktop.hidden"),
+            ],
+        ),
+        "Rename i3.desktop to i3.desktop.hidden",
+    ); 
+    soft_exec_eval(
+        exec_chroot(
+            "mv",
+            vec![
+                String::from("/usr/share/xsessions/i3-with-shmlog.desktop"),
+                String::from("/usr/share/xsessions/i3-with-shmlog.desktop.hidden"),
+            ],
+        ),
+        "Rename i3-with-shmlog.desktop to i3-with-shmlog.desktop.hidden",
+    ); 
+}
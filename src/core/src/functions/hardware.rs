@@ -0,0 +1,416 @@
+use crate::types::Microcode;
+use crate::internal::manifest;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const PCI_DEVICES: &str = "/sys/bus/pci/devices";
+const PCI_VENDOR_NVIDIA: u16 = 0x10de;
+const PCI_VENDOR_AMD: u16 = 0x1002;
+const PCI_VENDOR_INTEL: u16 = 0x8086;
+/// PCI base class of display controllers (VGA, 3D, other display).
+const PCI_CLASS_DISPLAY: u32 = 0x03;
+
+/// CPU vendors we know how to ship microcode for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CpuVendor {
+    Intel,
+    Amd,
+    Unknown,
+}
+
+/// Reads the `vendor_id` of the first CPU in `/proc/cpuinfo`.
+pub fn cpu_vendor() -> CpuVendor {
+    let cpuinfo = std::fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+    let vendor_id = cpuinfo
+        .lines()
+        .find(|line| line.starts_with("vendor_id"))
+        .and_then(|line| line.split(':').nth(1))
+        .map(|vendor| vendor.trim())
+        .unwrap_or_default();
+    match vendor_id {
+        "GenuineIntel" => CpuVendor::Intel,
+        "AuthenticAMD" => CpuVendor::Amd,
+        _ => CpuVendor::Unknown,
+    }
+}
+
+/// ### Returns the microcode package to install, if any.
+/// `Microcode::Auto` detects the vendor of the running CPU, the other values
+/// force a choice, which is useful when building an image for another machine.
+pub fn microcode_package(microcode: Microcode) -> Option<&'static str> {
+    let vendor = match microcode {
+        Microcode::Auto => cpu_vendor(),
+        Microcode::Intel => CpuVendor::Intel,
+        Microcode::Amd => CpuVendor::Amd,
+        Microcode::None => return None,
+    };
+    log::debug!("Microcode for CPU vendor {:?}", vendor);
+    match vendor {
+        CpuVendor::Intel => Some("intel-ucode"),
+        CpuVendor::Amd => Some("amd-ucode"),
+        CpuVendor::Unknown => {
+            log::warn!("Unknown CPU vendor, not installing any microcode");
+            None
+        }
+    }
+}
+
+/// Hypervisors we ship guest tools for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Hypervisor {
+    /// Bare metal
+    None,
+    VirtualBox,
+    Vmware,
+    Qemu,
+    HyperV,
+    /// A hypervisor we have no guest tools for
+    Other,
+}
+
+impl Hypervisor {
+    pub fn is_virtual(&self) -> bool {
+        *self != Hypervisor::None
+    }
+
+    /// The guest packages and the services to enable for them.
+    pub fn guest_tools(&self) -> (Vec<String>, Vec<String>) {
+        let (packages, services): (&[&str], &[&str]) = match self {
+            Hypervisor::VirtualBox => (&["virtualbox-guest-utils"], &["vboxservice"]),
+            Hypervisor::Vmware => (
+                &["open-vm-tools", "xf86-video-vmware", "gtkmm3"],
+                &["vmtoolsd", "vmware-vmblock-fuse"],
+            ),
+            Hypervisor::Qemu => (
+                &["qemu-guest-agent", "spice-vdagent"],
+                &["qemu-guest-agent"],
+            ),
+            Hypervisor::HyperV => (&["hyperv"], &["hv_kvp_daemon", "hv_vss_daemon"]),
+            Hypervisor::None | Hypervisor::Other => (&[], &[]),
+        };
+        (
+            packages.iter().map(|p| p.to_string()).collect(),
+            services.iter().map(|s| s.to_string()).collect(),
+        )
+    }
+}
+
+/// ### Detects the hypervisor we are running under, if any.
+/// The DMI vendor and product name identify the well known ones, the `hypervisor`
+/// CPU flag catches the rest.
+pub fn detect_hypervisor() -> Hypervisor {
+    let read_dmi = |name: &str| {
+        std::fs::read_to_string(Path::new("/sys/class/dmi/id").join(name))
+            .map(|value| value.trim().to_string())
+            .unwrap_or_default()
+    };
+    let sys_vendor = read_dmi("sys_vendor");
+    let product_name = read_dmi("product_name");
+    let hypervisor = match (sys_vendor.as_str(), product_name.as_str()) {
+        ("innotek GmbH" | "Oracle Corporation", _) => Hypervisor::VirtualBox,
+        (_, "VirtualBox") => Hypervisor::VirtualBox,
+        ("VMware, Inc.", _) => Hypervisor::Vmware,
+        ("QEMU", _) => Hypervisor::Qemu,
+        (_, product) if product.contains("KVM") || product.starts_with("Standard PC") => {
+            Hypervisor::Qemu
+        }
+        ("Microsoft Corporation", "Virtual Machine") => Hypervisor::HyperV,
+        _ if cpu_flags().iter().any(|flag| flag == "hypervisor") => Hypervisor::Other,
+        _ => Hypervisor::None,
+    };
+    log::debug!(
+        "Hypervisor {:?} (sys_vendor: {}, product_name: {})",
+        hypervisor,
+        sys_vendor,
+        product_name
+    );
+    hypervisor
+}
+
+/// Reads the `flags` of the first CPU in `/proc/cpuinfo`.
+fn cpu_flags() -> Vec<String> {
+    std::fs::read_to_string("/proc/cpuinfo")
+        .unwrap_or_default()
+        .lines()
+        .find(|line| line.starts_with("flags"))
+        .and_then(|line| line.split(':').nth(1))
+        .map(|flags| flags.split_whitespace().map(String::from).collect())
+        .unwrap_or_default()
+}
+
+/// How the machine was booted.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Firmware {
+    Uefi,
+    Bios,
+}
+
+/// The live system was booted in UEFI mode if the kernel exposes `/sys/firmware/efi`.
+pub fn detect_firmware() -> Firmware {
+    if Path::new("/sys/firmware/efi").exists() {
+        Firmware::Uefi
+    } else {
+        Firmware::Bios
+    }
+}
+
+/// The kind of machine, from the SMBIOS chassis type.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Chassis {
+    Laptop,
+    Desktop,
+    Server,
+    Unknown,
+}
+
+/// ### Detects the chassis of the machine.
+/// Reads `/sys/class/dmi/id/chassis_type`, and treats anything with a battery
+/// in `/sys/class/power_supply` as a laptop unless it is a server.
+pub fn detect_chassis() -> Chassis {
+    let chassis_type: u32 = std::fs::read_to_string("/sys/class/dmi/id/chassis_type")
+        .ok()
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(0);
+    let chassis = match chassis_type {
+        // Portable, Laptop, Notebook, Hand Held, Sub Notebook, Tablet, Convertible, Detachable
+        8 | 9 | 10 | 11 | 14 | 30 | 31 | 32 => Chassis::Laptop,
+        // Main Server Chassis, Rack Mount, Multi-system, Blade, Blade Enclosure
+        17 | 23 | 25 | 28 | 29 => Chassis::Server,
+        // Desktop, Low Profile Desktop, Pizza Box, Mini Tower, Tower, All in One,
+        // Space-saving, Lunch Box, Sealed-case PC, Mini PC, Stick PC
+        3 | 4 | 5 | 6 | 7 | 13 | 15 | 16 | 24 | 35 | 36 => Chassis::Desktop,
+        _ => Chassis::Unknown,
+    };
+    let chassis = if chassis != Chassis::Server && has_battery() {
+        Chassis::Laptop
+    } else {
+        chassis
+    };
+    log::debug!("Chassis {:?} (chassis_type: {})", chassis, chassis_type);
+    chassis
+}
+
+fn has_battery() -> bool {
+    std::fs::read_dir("/sys/class/power_supply")
+        .map(|entries| {
+            entries
+                .flatten()
+                .any(|entry| entry.file_name().to_string_lossy().starts_with("BAT"))
+        })
+        .unwrap_or(false)
+}
+
+/// A display controller found on the PCI bus.
+#[derive(Debug, Clone, Serialize)]
+pub struct PciDevice {
+    /// The PCI address, e.g. `0000:01:00.0`
+    pub slot: String,
+    pub vendor: u16,
+    pub device: u16,
+    pub class: u32,
+    /// The kernel driver currently bound to the device on the live system, if any
+    pub driver: Option<String>,
+}
+
+/// The driver stack to install for a GPU. The packages of each stack live in the
+/// `graphics` and `nvidia` sets of the manifests.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GpuDriver {
+    /// NVIDIA open kernel modules, Turing and newer
+    NvidiaOpen,
+    /// NVIDIA proprietary modules, Maxwell to Volta
+    NvidiaProprietary,
+    /// NVIDIA 470xx legacy branch, Kepler
+    NvidiaLegacy,
+    /// Anything NVIDIA older than Kepler
+    Nouveau,
+    Amdgpu,
+    Radeon,
+    /// Intel Broadwell and newer, driven by intel-media-driver
+    Intel,
+    /// Intel before Broadwell, driven by libva-intel-driver
+    IntelLegacy,
+    /// Unknown vendor, fall back to the generic drivers
+    Generic,
+}
+
+impl GpuDriver {
+    pub fn is_nvidia(&self) -> bool {
+        matches!(
+            self,
+            GpuDriver::NvidiaOpen | GpuDriver::NvidiaProprietary | GpuDriver::NvidiaLegacy
+        )
+    }
+}
+
+/// A detected GPU and the driver picked for it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Gpu {
+    #[serde(flatten)]
+    pub device: PciDevice,
+    pub driver_stack: GpuDriver,
+}
+
+/// The result of the hardware detection, as printed by `detect-hardware --json`.
+#[derive(Debug, Clone, Serialize)]
+pub struct HardwareReport {
+    pub cpu_vendor: CpuVendor,
+    pub microcode: Option<&'static str>,
+    pub hypervisor: Hypervisor,
+    pub chassis: Chassis,
+    pub gpus: Vec<Gpu>,
+    /// Packages needed by the detected GPUs, when the NVIDIA drivers are wanted
+    pub gpu_packages: Vec<String>,
+    pub blacklist_nouveau: bool,
+    /// An integrated GPU paired with an NVIDIA one (Optimus)
+    pub hybrid: bool,
+}
+
+/// Runs every hardware probe of the installer.
+pub fn detect() -> HardwareReport {
+    let gpus = detect_gpus();
+    HardwareReport {
+        cpu_vendor: cpu_vendor(),
+        microcode: microcode_package(Microcode::Auto),
+        hypervisor: detect_hypervisor(),
+        chassis: detect_chassis(),
+        gpu_packages: gpu_packages(&gpus, true),
+        blacklist_nouveau: gpus.iter().any(|gpu| gpu.driver_stack.is_nvidia()),
+        hybrid: is_hybrid(&gpus),
+        gpus,
+    }
+}
+
+/// Enumerates the display controllers from `/sys/bus/pci/devices`.
+pub fn display_controllers() -> Vec<PciDevice> {
+    let mut devices: Vec<PciDevice> = std::fs::read_dir(PCI_DEVICES)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|path| read_pci_device(path))
+        .filter(|device| device.class >> 16 == PCI_CLASS_DISPLAY)
+        .collect();
+    devices.sort_by(|a, b| a.slot.cmp(&b.slot));
+    devices
+}
+
+fn read_pci_device(path: &Path) -> Option<PciDevice> {
+    let read_hex = |name: &str| {
+        let value = std::fs::read_to_string(path.join(name)).ok()?;
+        u32::from_str_radix(value.trim().trim_start_matches("0x"), 16).ok()
+    };
+    Some(PciDevice {
+        slot: path.file_name()?.to_string_lossy().to_string(),
+        vendor: read_hex("vendor")? as u16,
+        device: read_hex("device")? as u16,
+        class: read_hex("class")?,
+        driver: std::fs::read_link(path.join("driver"))
+            .ok()
+            .and_then(|link| {
+                link.file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+            }),
+    })
+}
+
+/// Detects the GPUs and picks a driver stack for each of them.
+pub fn detect_gpus() -> Vec<Gpu> {
+    display_controllers()
+        .into_iter()
+        .map(|device| {
+            let driver_stack = pick_driver(&device);
+            log::debug!(
+                "GPU {} [{:04x}:{:04x}] uses {:?}",
+                device.slot,
+                device.vendor,
+                device.device,
+                driver_stack
+            );
+            Gpu {
+                device,
+                driver_stack,
+            }
+        })
+        .collect()
+}
+
+fn pick_driver(device: &PciDevice) -> GpuDriver {
+    match device.vendor {
+        PCI_VENDOR_NVIDIA => nvidia_driver(device.device),
+        // The live kernel binds Southern/Sea Islands and older cards to radeon,
+        // which is the most reliable hint we have for pre-GCN hardware
+        PCI_VENDOR_AMD if device.driver.as_deref() == Some("radeon") => GpuDriver::Radeon,
+        PCI_VENDOR_AMD => GpuDriver::Amdgpu,
+        PCI_VENDOR_INTEL if intel_is_legacy(device.device) => GpuDriver::IntelLegacy,
+        PCI_VENDOR_INTEL => GpuDriver::Intel,
+        _ => GpuDriver::Generic,
+    }
+}
+
+/// Picks the NVIDIA branch from the device ID, which is allocated per chip generation.
+fn nvidia_driver(device: u16) -> GpuDriver {
+    match device {
+        // Turing (TU1xx) and everything after it
+        0x1e00..=0xffff => GpuDriver::NvidiaOpen,
+        // Maxwell (GM10x/GM20x), Pascal and Volta
+        0x1340..=0x1dff => GpuDriver::NvidiaProprietary,
+        // Kepler (GK10x, GK110, GK208)
+        0x0fc0..=0x103f | 0x1180..=0x12ff => GpuDriver::NvidiaLegacy,
+        _ => GpuDriver::Nouveau,
+    }
+}
+
+/// Intel GPUs older than Broadwell are not supported by intel-media-driver.
+fn intel_is_legacy(device: u16) -> bool {
+    match device {
+        // Cherryview/Braswell is Gen8
+        0x22b0..=0x22b3 => false,
+        // Pineview
+        0xa001 | 0xa002 | 0xa011 | 0xa012 => true,
+        // GMA, Ironlake, Sandy/Ivy Bridge, Haswell, Bay Trail
+        0x0000..=0x15ff | 0x2000..=0x2fff => true,
+        _ => false,
+    }
+}
+
+/// Whether an NVIDIA GPU sits next to another display controller, as on Optimus laptops.
+pub fn is_hybrid(gpus: &[Gpu]) -> bool {
+    gpus.len() >= 2
+        && gpus.iter().any(|gpu| gpu.device.vendor == PCI_VENDOR_NVIDIA)
+        && gpus.iter().any(|gpu| gpu.device.vendor != PCI_VENDOR_NVIDIA)
+}
+
+/// ### Returns the graphics packages for the detected GPUs.
+/// When `with_nvidia` is false, NVIDIA GPUs get nouveau instead of the NVIDIA drivers.
+pub fn gpu_packages(gpus: &[Gpu], with_nvidia: bool) -> Vec<String> {
+    let mut facts = manifest::facts();
+    facts.gpus = gpus
+        .iter()
+        .map(|gpu| {
+            if gpu.driver_stack.is_nvidia() && !with_nvidia {
+                GpuDriver::Nouveau
+            } else {
+                gpu.driver_stack
+            }
+        })
+        .collect();
+    let mut packages = manifest::select_with("graphics", &facts).packages;
+    if with_nvidia {
+        for package in manifest::select_with("nvidia", &facts).packages {
+            if !packages.contains(&package) {
+                packages.push(package);
+            }
+        }
+    }
+    packages
+}
@@ -0,0 +1,451 @@
+use crate::types::InitramfsGenerator;
+use crate::internal::exec::*;
+use crate::internal::*;
+use lazy_static::lazy_static;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+const MKINITCPIO_CONF: &str = "etc/mkinitcpio.conf";
+/// Sourced by mkinitcpio after `mkinitcpio.conf`, keeps what the installation needs across runs.
+const MKINITCPIO_DROP_IN: &str = "etc/mkinitcpio.conf.d/10-axinstall.conf";
+const DRACUT_CONF: &str = "etc/dracut.conf.d/axinstall.conf";
+
+/// Features that need something from the initramfs to boot.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Feature {
+    Plymouth,
+    Encryption,
+    Lvm,
+    Raid,
+    Resume,
+}
+
+/// Where a hook has to live relative to the `block` and `filesystems` hooks.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Placement {
+    /// Right after `udev`/`systemd`, before anything touches block devices.
+    AfterInit,
+    /// After `block`, before `filesystems`, in the order of [`HOOK_ORDER`].
+    BeforeFilesystems,
+}
+
+/// Known hooks and their placement. Hooks sharing a placement are inserted
+/// in this order, so that e.g. `lvm2` can see devices opened by `encrypt`.
+const HOOK_ORDER: &[(&str, Placement)] = &[
+    ("plymouth", Placement::AfterInit),
+    ("mdadm_udev", Placement::BeforeFilesystems),
+    ("encrypt", Placement::BeforeFilesystems),
+    ("sd-encrypt", Placement::BeforeFilesystems),
+    ("lvm2", Placement::BeforeFilesystems),
+    ("resume", Placement::BeforeFilesystems),
+];
+
+#[derive(Default)]
+struct Requirements {
+    features: Vec<Feature>,
+    hooks: Vec<String>,
+    modules: Vec<String>,
+}
+
+lazy_static! {
+    static ref REQUIREMENTS: Mutex<Requirements> = Mutex::new(Requirements::default());
+    /// Where the configuration of the target is read and written, tests use a scratch directory.
+    static ref ROOT: Mutex<PathBuf> = Mutex::new(PathBuf::from("/mnt"));
+}
+
+/// The path of `file` in the target.
+fn target_path(file: &str) -> String {
+    ROOT.lock()
+        .unwrap()
+        .join(file)
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Registers a feature whose hooks must be part of the initramfs.
+pub fn require(feature: Feature) {
+    let mut requirements = REQUIREMENTS.lock().unwrap();
+    if !requirements.features.contains(&feature) {
+        log::debug!("initramfs: requiring {:?}", feature);
+        requirements.features.push(feature);
+    }
+}
+
+/// Registers an extra mkinitcpio hook by name.
+/// Known hooks are mapped to their [`Feature`] so they follow the udev/systemd flavour of the image.
+pub fn require_hook(hook: &str) {
+    match hook {
+        "plymouth" => return require(Feature::Plymouth),
+        "encrypt" | "sd-encrypt" => return require(Feature::Encryption),
+        "lvm2" => return require(Feature::Lvm),
+        "mdadm_udev" => return require(Feature::Raid),
+        "resume" => return require(Feature::Resume),
+        _ => {}
+    }
+    let mut requirements = REQUIREMENTS.lock().unwrap();
+    if !requirements.hooks.iter().any(|h| h == hook) {
+        log::debug!("initramfs: requiring hook {}", hook);
+        requirements.hooks.push(String::from(hook));
+    }
+}
+
+/// Registers a kernel module that must be loaded early from the initramfs.
+pub fn require_module(module: &str) {
+    let mut requirements = REQUIREMENTS.lock().unwrap();
+    if !requirements.modules.iter().any(|m| m == module) {
+        log::debug!("initramfs: requiring module {}", module);
+        requirements.modules.push(String::from(module));
+    }
+}
+
+/// Returns the packages needed by `generator` without installing them.
+pub fn generator_packages(generator: InitramfsGenerator) -> Vec<String> {
+    match generator {
+        InitramfsGenerator::Mkinitcpio => Vec::new(),
+        InitramfsGenerator::Dracut => vec![String::from("dracut")],
+    }
+}
+
+/// ### Writes the hooks and modules required so far to the mkinitcpio drop-in of the target.
+/// Those of an earlier run are kept, so that a later `initramfs` run, even from another process,
+/// still includes them. Steps requiring something call it once they are done.
+pub fn save() -> Result<()> {
+    load();
+    let requirements = REQUIREMENTS.lock().unwrap();
+    write_drop_in(&requirements)
+}
+
+/// ### Writes the collected hooks and modules and regenerates the initramfs.
+/// This is meant to run once, at the very end of the installation, after every
+/// step had a chance to call [`require`], [`require_hook`] or [`require_module`].
+/// What earlier runs saved with [`save`] is required too.
+pub fn regenerate(generator: InitramfsGenerator) -> Result<()> {
+    load();
+    let requirements = REQUIREMENTS.lock().unwrap();
+    match generator {
+        InitramfsGenerator::Mkinitcpio => {
+            write_drop_in(&requirements)?;
+            exec_eval(
+                exec_chroot("mkinitcpio", vec![String::from("-P")]),
+                "Regenerate initramfs with mkinitcpio",
+            )?;
+        }
+        InitramfsGenerator::Dracut => {
+            install(generator_packages(generator), true)?;
+            configure_dracut(&requirements)?;
+            exec_eval(
+                exec_chroot(
+                    "dracut",
+                    vec![String::from("--regenerate-all"), String::from("--force")],
+                ),
+                "Regenerate initramfs with dracut",
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Requires again the hooks and modules of the drop-in written by an earlier run, if any.
+fn load() {
+    let drop_in = match std::fs::read_to_string(target_path(MKINITCPIO_DROP_IN)) {
+        Ok(drop_in) => drop_in,
+        Err(_) => return,
+    };
+    let (hooks, modules) = parse_drop_in(&drop_in, &base_hooks());
+    for hook in hooks {
+        require_hook(&hook);
+    }
+    for module in modules {
+        require_module(&module);
+    }
+}
+
+/// The hooks of the target's `mkinitcpio.conf`, or mkinitcpio's defaults.
+fn base_hooks() -> Vec<String> {
+    std::fs::read_to_string(target_path(MKINITCPIO_CONF))
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim_start)
+        .find(|line| line.starts_with("HOOKS="))
+        .map(parse_array)
+        .unwrap_or_else(|| {
+            [
+                "base",
+                "udev",
+                "autodetect",
+                "microcode",
+                "modconf",
+                "kms",
+                "keyboard",
+                "keymap",
+                "consolefont",
+                "block",
+                "filesystems",
+                "fsck",
+            ]
+            .iter()
+            .map(|h| h.to_string())
+            .collect()
+        })
+}
+
+/// ### The drop-in for `requirements`.
+/// Modules are added to those of `mkinitcpio.conf`, hooks are inserted into its list, which the drop-in then replaces.
+fn drop_in(requirements: &Requirements, base_hooks: Vec<String>) -> String {
+    let mut drop_in =
+        String::from("# Hooks and modules needed by the installation, written by axinstall\n");
+    if !requirements.modules.is_empty() {
+        drop_in.push_str(&format!("MODULES+=({})\n", requirements.modules.join(" ")));
+    }
+    let hooks = insert_hooks(base_hooks.clone(), requirements);
+    if hooks != base_hooks {
+        drop_in.push_str(&format!("HOOKS=({})\n", hooks.join(" ")));
+    }
+    drop_in
+}
+
+/// The hooks and modules of a drop-in, the hooks being those missing from `base_hooks`.
+fn parse_drop_in(drop_in: &str, base_hooks: &[String]) -> (Vec<String>, Vec<String>) {
+    let mut hooks = Vec::new();
+    let mut modules = Vec::new();
+    for line in drop_in.lines().map(str::trim_start) {
+        if line.starts_with("MODULES+=") {
+            modules = parse_array(line);
+        } else if line.starts_with("HOOKS=") {
+            hooks = parse_array(line)
+                .into_iter()
+                .filter(|hook| !base_hooks.contains(hook))
+                .collect();
+        }
+    }
+    (hooks, modules)
+}
+
+fn write_drop_in(requirements: &Requirements) -> Result<()> {
+    files_eval(
+        files::write_file(
+            &target_path(MKINITCPIO_DROP_IN),
+            &drop_in(requirements, base_hooks()),
+        ),
+        "Write initramfs hooks and modules to the mkinitcpio drop-in",
+    )
+}
+
+fn configure_dracut(requirements: &Requirements) -> Result<()> {
+    let dracut_modules: Vec<&str> = required_hooks(requirements, false)
+        .iter()
+        .filter_map(|hook| match hook.as_str() {
+            "plymouth" => Some("plymouth"),
+            "encrypt" => Some("crypt"),
+            "lvm2" => Some("lvm"),
+            "mdadm_udev" => Some("mdraid"),
+            "resume" => Some("resume"),
+            other => {
+                log::warn!(
+                    "No dracut equivalent for mkinitcpio hook {}, skipping",
+                    other
+                );
+                None
+            }
+        })
+        .collect();
+    files_eval(
+        files::write_file(
+            &target_path(DRACUT_CONF),
+            &format!(
+                "add_dracutmodules+=\" {} \"\nadd_drivers+=\" {} \"\n",
+                dracut_modules.join(" "),
+                requirements.modules.join(" "),
+            ),
+        ),
+        "Write dracut configuration",
+    )
+}
+
+/// Parses a bash array assignment such as `HOOKS=(base udev block)`.
+fn parse_array(line: &str) -> Vec<String> {
+    let value = line.split_once('=').map(|(_, v)| v).unwrap_or_default();
+    let value = value.split('#').next().unwrap_or_default();
+    value
+        .trim()
+        .trim_start_matches('(')
+        .trim_end_matches(')')
+        .trim_matches('"')
+        .split_whitespace()
+        .map(String::from)
+        .collect()
+}
+
+/// Turns the registered features into hook names, honouring systemd-based images.
+fn required_hooks(requirements: &Requirements, systemd: bool) -> Vec<String> {
+    let mut hooks: Vec<String> = requirements
+        .features
+        .iter()
+        .filter_map(|feature| match feature {
+            Feature::Plymouth => Some("plymouth"),
+            Feature::Encryption if systemd => Some("sd-encrypt"),
+            Feature::Encryption => Some("encrypt"),
+            Feature::Lvm => Some("lvm2"),
+            Feature::Raid => Some("mdadm_udev"),
+            // systemd resumes from hibernation on its own
+            Feature::Resume if systemd => None,
+            Feature::Resume => Some("resume"),
+        })
+        .map(String::from)
+        .collect();
+    for hook in &requirements.hooks {
+        if !hooks.contains(hook) {
+            hooks.push(hook.clone());
+        }
+    }
+    hooks
+}
+
+/// Inserts the required hooks into an existing hook list, relative to
+/// `udev`/`systemd`, `block` and `filesystems`. Hooks already present are kept in place.
+fn insert_hooks(mut hooks: Vec<String>, requirements: &Requirements) -> Vec<String> {
+    let systemd = hooks.iter().any(|h| h == "systemd");
+    let mut required = required_hooks(requirements, systemd);
+    required.retain(|hook| !hooks.contains(hook));
+    required.sort_by_key(|hook| {
+        HOOK_ORDER
+            .iter()
+            .position(|(name, _)| name == hook)
+            .unwrap_or(HOOK_ORDER.len())
+    });
+
+    for hook in required {
+        let placement = HOOK_ORDER
+            .iter()
+            .find(|(name, _)| *name == hook)
+            .map(|(_, placement)| *placement)
+            .unwrap_or(Placement::BeforeFilesystems);
+        let index = match placement {
+            Placement::AfterInit => hooks
+                .iter()
+                .position(|h| h == "udev" || h == "systemd")
+                .map(|i| i + 1)
+                .unwrap_or(hooks.len().min(1)),
+            Placement::BeforeFilesystems => hooks
+                .iter()
+                .position(|h| h == "filesystems")
+                .unwrap_or(hooks.len()),
+        };
+        hooks.insert(index, hook);
+    }
+    hooks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::runner::tests::{chroot, record};
+    use crate::internal::runner::Operation;
+
+    fn hooks(hooks: &str) -> Vec<String> {
+        hooks.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn drop_in_keeps_the_requirements_of_an_earlier_run() {
+        let requirements = Requirements {
+            features: vec![Feature::Encryption, Feature::Plymouth],
+            hooks: vec![String::from("netconf")],
+            modules: vec![String::from("nvidia"), String::from("nvidia_drm")],
+        };
+        let base = hooks("base systemd autodetect block filesystems fsck");
+        let drop_in = drop_in(&requirements, base.clone());
+        assert_eq!(
+            drop_in,
+            "# Hooks and modules needed by the installation, written by axinstall\n\
+             MODULES+=(nvidia nvidia_drm)\n\
+             HOOKS=(base systemd plymouth autodetect block sd-encrypt netconf filesystems fsck)\n"
+        );
+        assert_eq!(
+            parse_drop_in(&drop_in, &base),
+            (
+                hooks("plymouth sd-encrypt netconf"),
+                hooks("nvidia nvidia_drm")
+            )
+        );
+    }
+
+    #[test]
+    fn drop_in_leaves_the_hooks_alone_when_none_is_required() {
+        let requirements = Requirements {
+            modules: vec![String::from("i915")],
+            ..Default::default()
+        };
+        let drop_in = drop_in(&requirements, hooks("base udev block filesystems"));
+        assert!(!drop_in.contains("HOOKS"), "{drop_in}");
+    }
+
+    /// Runs `f` on a scratch target holding `mkinitcpio_conf`, with nothing required yet.
+    fn on_scratch_target(mkinitcpio_conf: &str, f: impl FnOnce(&PathBuf)) {
+        let root = std::env::temp_dir().join(format!("axinstall-initramfs-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("etc")).unwrap();
+        std::fs::write(root.join(MKINITCPIO_CONF), mkinitcpio_conf).unwrap();
+        *ROOT.lock().unwrap() = root.clone();
+        *REQUIREMENTS.lock().unwrap() = Requirements::default();
+        f(&root);
+        *ROOT.lock().unwrap() = PathBuf::from("/mnt");
+        *REQUIREMENTS.lock().unwrap() = Requirements::default();
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn mkinitcpio_gets_the_required_modules_and_hooks() {
+        let mut drop_in_path = PathBuf::new();
+        let recorder = record(|| {
+            on_scratch_target(
+                "MODULES=()\nHOOKS=(base udev autodetect block filesystems fsck)\n",
+                |root| {
+                    drop_in_path = root.join(MKINITCPIO_DROP_IN);
+                    require(Feature::Plymouth);
+                    require_module("nvidia");
+                    regenerate(InitramfsGenerator::Mkinitcpio).unwrap();
+                },
+            )
+        });
+        recorder.assert_operations(&[
+            Operation::WriteFile {
+                path: drop_in_path,
+                contents: String::from(
+                    "# Hooks and modules needed by the installation, written by axinstall\n\
+                     MODULES+=(nvidia)\n\
+                     HOOKS=(base udev plymouth autodetect block filesystems fsck)\n",
+                ),
+            },
+            chroot(&["mkinitcpio", "-P"], None),
+        ]);
+    }
+
+    #[test]
+    fn regenerating_keeps_an_earlier_drop_in() {
+        let mut drop_in_path = PathBuf::new();
+        let recorder = record(|| {
+            on_scratch_target("HOOKS=(base udev block filesystems)\n", |root| {
+                drop_in_path = root.join(MKINITCPIO_DROP_IN);
+                std::fs::create_dir_all(drop_in_path.parent().unwrap()).unwrap();
+                std::fs::write(
+                    &drop_in_path,
+                    "MODULES+=(i915)\nHOOKS=(base udev block lvm2 filesystems)\n",
+                )
+                .unwrap();
+                require_module("nvidia");
+                regenerate(InitramfsGenerator::Mkinitcpio).unwrap();
+            })
+        });
+        recorder.assert_operations(&[
+            Operation::WriteFile {
+                path: drop_in_path,
+                contents: String::from(
+                    "# Hooks and modules needed by the installation, written by axinstall\n\
+                     MODULES+=(nvidia i915)\n\
+                     HOOKS=(base udev block lvm2 filesystems)\n",
+                ),
+            },
+            chroot(&["mkinitcpio", "-P"], None),
+        ]);
+    }
+}
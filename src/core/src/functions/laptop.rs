@@ -0,0 +1,207 @@
+use crate::types::PowerDaemon;
+use crate::functions::hardware::{self, Chassis, CpuVendor};
+use crate::internal::exec::*;
+use crate::internal::*;
+
+/// ### Sets up a laptop: power daemon, lid switch and touchpad.
+/// Desktops and servers are left untouched.
+pub fn setup_laptop(power_daemon: PowerDaemon) -> Result<()> {
+    let chassis = hardware::detect_chassis();
+    if chassis != Chassis::Laptop {
+        log::info!("Chassis is {:?}, skipping laptop setup", chassis);
+        return Ok(());
+    }
+    log::info!("Laptop detected, setting up {:?}", power_daemon);
+    let (packages, services) = power_stack(power_daemon);
+    install(packages, true)?;
+    for service in services {
+        exec_eval(
+            exec_chroot("systemctl", vec![String::from("enable"), String::from(service)]),
+            format!("Enable {}", service).as_str(),
+        )?;
+    }
+    if power_daemon == PowerDaemon::Tlp {
+        // TLP handles the radios itself, as documented upstream
+        exec_eval(
+            exec_chroot(
+                "systemctl",
+                vec![
+                    String::from("mask"),
+                    String::from("systemd-rfkill.service"),
+                    String::from("systemd-rfkill.socket"),
+                ],
+            ),
+            "Mask systemd-rfkill for TLP",
+        )?;
+    }
+    set_lid_switch()?;
+    set_touchpad_defaults()
+}
+
+/// Returns the laptop packages without installing them, nothing on desktops and servers.
+pub fn laptop_packages(power_daemon: PowerDaemon) -> Vec<String> {
+    if hardware::detect_chassis() != Chassis::Laptop {
+        return Vec::new();
+    }
+    power_stack(power_daemon).0
+}
+
+/// The packages and services of the power daemon, plus thermald on Intel.
+fn power_stack(power_daemon: PowerDaemon) -> (Vec<String>, Vec<&'static str>) {
+    let mut packages = vec![String::from("acpi")];
+    let mut services: Vec<&str> = Vec::new();
+    match power_daemon {
+        PowerDaemon::Ppd => {
+            packages.push(String::from("power-profiles-daemon"));
+            services.push("power-profiles-daemon");
+        }
+        PowerDaemon::Tlp => {
+            packages.push(String::from("tlp"));
+            packages.push(String::from("tlp-rdw"));
+            services.push("tlp");
+            services.push("NetworkManager-dispatcher");
+        }
+        PowerDaemon::AutoCpufreq => {
+            packages.push(String::from("auto-cpufreq"));
+            services.push("auto-cpufreq");
+        }
+    }
+    if hardware::cpu_vendor() == CpuVendor::Intel {
+        packages.push(String::from("thermald"));
+        services.push("thermald");
+    }
+    (packages, services)
+}
+
+/// Suspends when the lid is closed, unless the laptop is docked.
+fn set_lid_switch() -> Result<()> {
+    files_eval(
+        files::write_file(
+            "/mnt/etc/systemd/logind.conf.d/10-axinstall-lid.conf",
+            "[Login]\n\
+             HandleLidSwitch=suspend\n\
+             HandleLidSwitchExternalPower=suspend\n\
+             HandleLidSwitchDocked=ignore\n",
+        ),
+        "Set lid switch handling in logind",
+    )
+}
+
+/// ### Enables tap to click and disable while typing on touchpads.
+/// X11 sessions get them from `xorg.conf.d`, Plasma on Wayland from `kcminputrc`
+/// and Hyprland (Sleex) from a config snippet. The scrolling direction is left to the user.
+fn set_touchpad_defaults() -> Result<()> {
+    set_x11_touchpad_defaults()?;
+    set_kde_touchpad_defaults()?;
+    set_hyprland_touchpad_defaults()
+}
+
+/// Sets the touchpad defaults of X11 sessions, for every libinput touchpad.
+fn set_x11_touchpad_defaults() -> Result<()> {
+    files_eval(
+        files::write_file(
+            "/mnt/etc/X11/xorg.conf.d/30-touchpad.conf",
+            "Section \"InputClass\"\n\
+             Identifier \"touchpad\"\n\
+             MatchIsTouchpad \"on\"\n\
+             Driver \"libinput\"\n\
+             Option \"Tapping\" \"on\"\n\
+             Option \"DisableWhileTyping\" \"on\"\n\
+             EndSection\n",
+        ),
+        "Set X11 touchpad defaults",
+    )
+}
+
+/// ### Sets the touchpad defaults of Plasma in the system `kcminputrc`, read before the user's one.
+/// KWin keeps the settings per device, so the touchpads of this machine are written.
+fn set_kde_touchpad_defaults() -> Result<()> {
+    let devices = std::fs::read_to_string("/proc/bus/input/devices").unwrap_or_default();
+    let kcminputrc = kde_touchpad_defaults(&devices);
+    if kcminputrc.is_empty() {
+        log::info!("No touchpad found, skipping Plasma touchpad defaults");
+        return Ok(());
+    }
+    files_eval(
+        files::write_file("/mnt/etc/xdg/kcminputrc", &kcminputrc),
+        "Set Plasma touchpad defaults",
+    )
+}
+
+/// Returns the `kcminputrc` groups of the touchpads listed in `/proc/bus/input/devices`.
+fn kde_touchpad_defaults(devices: &str) -> String {
+    let mut groups = String::new();
+    for device in devices.split("\n\n") {
+        let mut ids = None;
+        let mut name = None;
+        for line in device.lines() {
+            if let Some(info) = line.strip_prefix("I: ") {
+                let id = |key: &str| {
+                    let value = info.split_whitespace().find_map(|f| f.strip_prefix(key))?;
+                    u32::from_str_radix(value, 16).ok()
+                };
+                ids = id("Vendor=").zip(id("Product="));
+            } else if let Some(n) = line.strip_prefix("N: Name=") {
+                name = Some(n.trim_matches('"'));
+            }
+        }
+        if let (Some((vendor, product)), Some(name)) = (ids, name) {
+            if name.to_lowercase().contains("touchpad") {
+                groups.push_str(&format!(
+                    "[Libinput][{vendor}][{product}][{name}]\nTapToClick=true\nDisableWhileTyping=true\n\n"
+                ));
+            }
+        }
+    }
+    groups
+}
+
+/// ### Sets the touchpad defaults of Hyprland, for Sleex.
+/// The snippet is sourced from the skeleton Hyprland config when there is one,
+/// so that the users created afterwards get it.
+fn set_hyprland_touchpad_defaults() -> Result<()> {
+    files_eval(
+        files::write_file(
+            "/mnt/etc/xdg/hypr/touchpad.conf",
+            "input {
+    touchpad {
+        tap-to-click = true
+        disable_while_typing = true
+    }
+}
+",
+        ),
+        "Set Hyprland touchpad defaults",
+    )?;
+    let skeleton = "/mnt/etc/skel/.config/hypr/hyprland.conf";
+    if !std::path::Path::new(skeleton).exists() {
+        log::debug!("No skeleton Hyprland config, touchpad defaults are not sourced");
+        return Ok(());
+    }
+    files_eval(
+        files::append_file(skeleton, "source = /etc/xdg/hypr/touchpad.conf"),
+        "Source Hyprland touchpad defaults",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kde_defaults_cover_every_touchpad() {
+        let devices = "I: Bus=0011 Vendor=0001 Product=0001 Version=ab83\n\
+                       N: Name=\"AT Translated Set 2 keyboard\"\n\
+                       H: Handlers=sysrq kbd event3 leds\n\
+                       \n\
+                       I: Bus=0018 Vendor=06cb Product=cd50 Version=0100\n\
+                       N: Name=\"SYNA7DB5:01 06CB:CD50 Touchpad\"\n\
+                       H: Handlers=mouse1 event9\n";
+        assert_eq!(
+            kde_touchpad_defaults(devices),
+            "[Libinput][1739][52560][SYNA7DB5:01 06CB:CD50 Touchpad]\n\
+             TapToClick=true\n\
+             DisableWhileTyping=true\n\n"
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use crate::internal::exec::*;
+use crate::internal::*;
+use std::path::{Path, PathBuf};
+
+/// Where archiso mounts the boot medium.
+const BOOTMNT: &str = "/run/archiso/bootmnt";
+
+/// Paths excluded when copying the running `/`, they are either virtual or mounted at runtime.
+const RSYNC_EXCLUDES: &[&str] = &[
+    "/dev/*",
+    "/proc/*",
+    "/sys/*",
+    "/run/*",
+    "/tmp/*",
+    "/mnt/*",
+    "/media/*",
+    "/lost+found",
+    "/etc/fstab",
+];
+
+/// archiso units and drop-ins that only make sense on the live medium.
+const LIVE_FILES: &[&str] = &[
+    "/mnt/etc/systemd/system/pacman-init.service",
+    "/mnt/etc/systemd/system/choose-mirror.service",
+    "/mnt/etc/systemd/system/etc-pacman.d-gnupg.mount",
+    "/mnt/etc/systemd/system/livecd-talk.service",
+    "/mnt/etc/systemd/system/livecd-alsa-unmuter.service",
+    "/mnt/etc/systemd/system/multi-user.target.wants/pacman-init.service",
+    "/mnt/etc/systemd/system/multi-user.target.wants/choose-mirror.service",
+    "/mnt/etc/systemd/system/multi-user.target.wants/livecd-talk.service",
+    "/mnt/etc/systemd/system/sound.target.wants/livecd-alsa-unmuter.service",
+    "/mnt/etc/systemd/system/getty@tty1.service.d/autologin.conf",
+    "/mnt/etc/systemd/journald.conf.d/volatile-storage.conf",
+    "/mnt/etc/systemd/logind.conf.d/do-not-suspend.conf",
+    "/mnt/etc/sddm.conf.d/autologin.conf",
+    "/mnt/etc/mkinitcpio.conf.d/archiso.conf",
+    "/mnt/etc/fstab",
+];
+
+/// ### Copies the live root filesystem into `/mnt` instead of installing the base with pacstrap.
+/// The airootfs squashfs from the boot medium is unpacked when found, the running `/` is copied otherwise.
+/// Then everything specific to the live medium is removed: archiso packages, units and hooks,
+/// the live user and autologin. The machine-id is reset and the kernels are put back in `/boot`.
+///
+/// The initramfs is not rebuilt here, it is regenerated by [`initramfs::regenerate`](super::initramfs::regenerate).
+pub fn copy_live_system() -> Result<()> {
+    match find_airootfs() {
+        Some(squashfs) => exec_eval(
+            exec(
+                "unsquashfs",
+                vec![
+                    String::from("-f"),
+                    String::from("-d"),
+                    String::from("/mnt"),
+                    squashfs.to_string_lossy().to_string(),
+                ],
+            ),
+            format!("Unpack live root filesystem {squashfs:?}").as_str(),
+        )?,
+        None => {
+            let mut args = vec![String::from("-aAXH")];
+            for exclude in RSYNC_EXCLUDES {
+                args.push(format!("--exclude={exclude}"));
+            }
+            args.push(String::from("/"));
+            args.push(String::from("/mnt"));
+            exec_eval(exec("rsync", args), "Copy running live root filesystem")?;
+        }
+    }
+
+    for path in LIVE_FILES {
+        if Path::new(path).exists() {
+            files_eval(files::remove_file(path), &format!("Remove live file {path}"))?;
+        }
+    }
+    remove_live_packages();
+    remove_live_users()?;
+    reset_machine_id()?;
+    restore_kernels()?;
+    install::mark_installed(installed_packages());
+    Ok(())
+}
+
+/// Finds the airootfs squashfs on the archiso boot medium, whatever the install directory is named.
+fn find_airootfs() -> Option<PathBuf> {
+    std::fs::read_dir(BOOTMNT)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path().join("x86_64/airootfs.sfs"))
+        .find(|path| path.exists())
+}
+
+/// Removes the packages of the `live-only` set that ended up in the target.
+fn remove_live_packages() {
+    let installed = installed_packages();
+    let live: Vec<String> = manifest::select("live-only")
+        .packages
+        .into_iter()
+        .filter(|p| installed.contains(p))
+        .collect();
+    if live.is_empty() {
+        return;
+    }
+    let mut args = vec![String::from("-Rns"), String::from("--noconfirm")];
+    args.extend(live.iter().cloned());
+    soft_exec_eval(
+        exec_chroot("pacman", args),
+        format!("Remove live packages {}", live.join(", ")).as_str(),
+    );
+}
+
+/// Deletes the users of the live medium, every regular account found in the copied `/etc/passwd`.
+fn remove_live_users() -> Result<()> {
+    let passwd = std::fs::read_to_string("/mnt/etc/passwd").unwrap_or_default();
+    for line in passwd.lines() {
+        let fields: Vec<&str> = line.split(':').collect();
+        if fields.len() < 3 {
+            continue;
+        }
+        let uid: u32 = fields[2].parse().unwrap_or(0);
+        // 65534 is nobody
+        if !(1000..65534).contains(&uid) {
+            continue;
+        }
+        exec_eval(
+            exec_chroot("userdel", vec![String::from("-r"), String::from(fields[0])]),
+            format!("Remove live user {}", fields[0]).as_str(),
+        )?;
+        let sudoers = format!("/mnt/etc/sudoers.d/{}", fields[0]);
+        if Path::new(&sudoers).exists() {
+            files_eval(files::remove_file(&sudoers), "Remove live user sudoers rule")?;
+        }
+    }
+    Ok(())
+}
+
+/// Empties the machine-id, systemd generates a new one on first boot.
+fn reset_machine_id() -> Result<()> {
+    files_eval(
+        files::write_file("/mnt/etc/machine-id", ""),
+        "Reset machine-id",
+    )?;
+    let _ = files::remove_file("/mnt/var/lib/dbus/machine-id");
+    Ok(())
+}
+
+/// ### Puts every kernel back in `/boot` with a regular mkinitcpio preset.
+/// archiso strips `/boot` from the airootfs and ships presets building its own images.
+fn restore_kernels() -> Result<()> {
+    files_eval(files::create_directory("/mnt/boot"), "Create /boot")?;
+    let modules = std::fs::read_dir("/mnt/usr/lib/modules").into_iter().flatten();
+    for entry in modules.flatten() {
+        let pkgbase = match std::fs::read_to_string(entry.path().join("pkgbase")) {
+            Ok(pkgbase) => pkgbase.trim().to_string(),
+            Err(_) => continue,
+        };
+        let vmlinuz = entry.path().join("vmlinuz");
+        files::copy_file(
+            &vmlinuz.to_string_lossy(),
+            &format!("/mnt/boot/vmlinuz-{pkgbase}"),
+        )?;
+        files_eval(
+            files::write_file(
+                &format!("/mnt/etc/mkinitcpio.d/{pkgbase}.preset"),
+                &format!(
+                    "# mkinitcpio preset file for the '{pkgbase}' package\n\n\
+                     ALL_kver=\"/boot/vmlinuz-{pkgbase}\"\n\n\
+                     PRESETS=('default' 'fallback')\n\n\
+                     default_image=\"/boot/initramfs-{pkgbase}.img\"\n\n\
+                     fallback_image=\"/boot/initramfs-{pkgbase}-fallback.img\"\n\
+                     fallback_options=\"-S autodetect\"\n"
+                ),
+            ),
+            format!("Write mkinitcpio preset for {pkgbase}").as_str(),
+        )?;
+    }
+    Ok(())
+}
+
+/// Lists the packages installed in the target.
+fn installed_packages() -> Vec<String> {
+    let output = Cmd::new("pacman").args(["--root", "/mnt", "-Qq"]).output();
+    match output {
+        Ok(output) => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(String::from)
+            .collect(),
+        Err(e) => {
+            log::warn!("Could not list the packages of the target: {}", e);
+            Vec::new()
+        }
+    }
+}
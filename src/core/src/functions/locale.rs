@@ -1,10 +1,55 @@
 use crate::internal::exec::*;
 use crate::internal::*;
 
+/// ### The locales glibc can generate, as `locale charset` pairs.
+/// Read from `/usr/share/i18n/SUPPORTED`, e.g. `en_US.UTF-8 UTF-8`.
+pub fn supported_locales() -> Vec<String> {
+    std::fs::read_to_string("/usr/share/i18n/SUPPORTED")
+        .unwrap_or_default()
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+}
+
+/// The console keymaps shipped by kbd, sorted by name.
+pub fn keymaps() -> Vec<String> {
+    let mut keymaps = Vec::new();
+    let mut dirs = vec![std::path::PathBuf::from("/usr/share/kbd/keymaps")];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir).into_iter().flatten().flatten() {
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if let Some(keymap) = name.strip_suffix(".map.gz") {
+                keymaps.push(keymap.to_string());
+            }
+        }
+    }
+    keymaps.sort();
+    keymaps.dedup();
+    keymaps
+}
+
+/// The timezones from `/usr/share/zoneinfo/zone1970.tab`, with UTC, sorted by name.
+pub fn timezones() -> Vec<String> {
+    let mut timezones: Vec<String> = std::fs::read_to_string("/usr/share/zoneinfo/zone1970.tab")
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split('\t').nth(2))
+        .map(String::from)
+        .collect();
+    timezones.push(String::from("UTC"));
+    timezones.sort();
+    timezones
+}
+
 /// Will set the timezone by creating a symlink between the `/usr/share/zoneinfo/wanted_timezone` and `/etc/localtime`.
 /// 
 /// Then, `hwclock` will sync the clock
-pub fn set_timezone(timezone: &str) {
+pub fn set_timezone(timezone: &str) -> Result<()> {
     exec_eval(
         exec_chroot(
             "ln",
@@ -15,24 +60,24 @@ pub fn set_timezone(timezone: &str) {
             ],
         ),
         "Set timezone",
-    );
+    )?;
     exec_eval(
         exec_chroot("hwclock", vec!["--systohc".to_string()]),
         "Set system clock",
-    );
+    )
 }
 
 /// Will set the system locale by appending values to locale.gen and locale.conf
-pub fn set_locale(locale: String) {
+pub fn set_locale(locale: String) -> Result<()> {
     files_eval(
         files::append_file("/mnt/etc/locale.gen", "en_US.UTF-8 UTF-8"),
         "add en_US.UTF-8 UTF-8 to locale.gen",
-    );
-    files::create_file("/mnt/etc/locale.conf");
+    )?;
+    files::create_file("/mnt/etc/locale.conf")?;
     files_eval(
         files::append_file("/mnt/etc/locale.conf", "LANG=en_US.UTF-8"),
         "edit locale.conf",
-    );
+    )?;
     for i in (0..locale.split(' ').count()).step_by(2) {
         files_eval(
             files::append_file(
@@ -44,7 +89,7 @@ pub fn set_locale(locale: String) {
                 ),
             ),
             "add locales to locale.gen",
-        );
+        )?;
         if locale.split(' ').collect::<Vec<&str>>()[i] != "en_US.UTF-8" {
             files_eval(
                 files::sed_file(
@@ -57,22 +102,22 @@ pub fn set_locale(locale: String) {
                     locale.split(' ').collect::<Vec<&str>>()[i]
                 )
                 .as_str(),
-            );
+            )?;
         }
     }
-    exec_eval(exec_chroot("locale-gen", vec![]), "generate locales");
+    exec_eval(exec_chroot("locale-gen", vec![]), "generate locales")
 }
 
 /// Will set the kb layout by editing `vconsole.conf` and `/etc/X11/xorg.conf.d/00-keyboard.conf`.
-pub fn set_keyboard(keyboard: &str) {
-    files::create_file("/mnt/etc/vconsole.conf");
+pub fn set_keyboard(keyboard: &str) -> Result<()> {
+    files::create_file("/mnt/etc/vconsole.conf")?;
     files_eval(
         files::append_file(
             "/mnt/etc/vconsole.conf",
             format!("KEYMAP={}", keyboard).as_str(),
         ),
         "set keyboard layout in vconsole",
-    );
+    )?;
 
     files_eval(
         files::write_file(
@@ -87,5 +132,5 @@ pub fn set_keyboard(keyboard: &str) {
             ),
         ),
         "set X11 keyboard layout",
-    );
+    )
 }
\ No newline at end of file
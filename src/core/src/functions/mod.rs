@@ -1,7 +1,12 @@
 pub mod base;
 pub mod desktops;
+pub mod hardware;
+pub mod initramfs;
+pub mod laptop;
+pub mod live;
 pub mod locale;
 pub mod network;
+pub mod pacman;
 pub mod partition;
 pub mod users;
 pub mod userkit;
\ No newline at end of file
@@ -1,25 +1,25 @@
 use crate::internal::*;
 
-pub fn set_hostname(hostname: &str) {
-    println!("Setting hostname to {}", hostname);
-    files::create_file("/mnt/etc/hostname");
+pub fn set_hostname(hostname: &str) -> Result<()> {
+    log::info!("Setting hostname to {}", hostname);
+    files::create_file("/mnt/etc/hostname")?;
     files_eval(
         files::append_file("/mnt/etc/hostname", hostname),
         "set hostname",
-    );
+    )
 }
 
-pub fn create_hosts() {
-    files::create_file("/mnt/etc/hosts");
+pub fn create_hosts() -> Result<()> {
+    files::create_file("/mnt/etc/hosts")?;
     files_eval(
         files::append_file("/mnt/etc/hosts", "127.0.0.1     localhost"),
         "create /etc/hosts",
-    );
+    )
 }
 
-pub fn enable_ipv6() {
+pub fn enable_ipv6() -> Result<()> {
     files_eval(
         files::append_file("/mnt/etc/hosts", "::1 localhost"),
         "add ipv6 localhost",
-    );
+    )
 }
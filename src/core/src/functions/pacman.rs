@@ -0,0 +1,285 @@
+use crate::internal::exec::*;
+use crate::internal::*;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+const LIVE_PACMAN_CONF: &str = "/etc/pacman.conf";
+const TARGET_PACMAN_CONF: &str = "/mnt/etc/pacman.conf";
+/// The generated `pacman.conf` as used from the live system, to check, download and install packages.
+const INSTALL_PACMAN_CONF: &str = "/tmp/axinstall-pacman.conf";
+/// Repositories handled by the toggles, any live section with these names is replaced.
+const TOGGLED_REPOS: &[&str] = &["multilib", "chaotic-aur"];
+
+/// ### How the target `pacman.conf` is generated.
+/// Without these settings, the live `pacman.conf` is copied verbatim.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PacmanSettings {
+    pub parallel_downloads: u32,
+    pub color: bool,
+    pub ilovecandy: bool,
+    pub multilib: bool,
+    pub chaotic_aur: bool,
+    pub repositories: Vec<Repository>,
+}
+
+impl Default for PacmanSettings {
+    fn default() -> Self {
+        PacmanSettings {
+            parallel_downloads: 5,
+            color: true,
+            ilovecandy: false,
+            multilib: true,
+            chaotic_aur: true,
+            repositories: Vec::new(),
+        }
+    }
+}
+
+/// An extra repository added after the official ones.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Repository {
+    pub name: String,
+    pub server: String,
+    /// e.g. `Optional TrustAll`, pacman's default when unset
+    #[serde(default)]
+    pub sig_level: Option<String>,
+    /// ID of the signing key, received from a keyserver unless `key_file` is set
+    #[serde(default)]
+    pub key: Option<String>,
+    /// Local file holding the signing key
+    #[serde(default)]
+    pub key_file: Option<PathBuf>,
+}
+
+lazy_static! {
+    static ref SETTINGS: Mutex<Option<PacmanSettings>> = Mutex::new(None);
+}
+
+/// Generates the target `pacman.conf` from `settings` instead of copying the live one.
+pub fn configure(settings: PacmanSettings) {
+    log::debug!("pacman.conf settings : {:?}", settings);
+    *SETTINGS.lock().unwrap() = Some(settings);
+}
+
+/// Goes back to copying the live `pacman.conf`, for tests that configured it.
+#[cfg(test)]
+pub(crate) fn unconfigure() {
+    *SETTINGS.lock().unwrap() = None;
+}
+
+/// ### Whether the target uses the Chaotic-AUR.
+/// With generated settings that is the toggle, otherwise whether the live `pacman.conf` has the repository.
+fn chaotic_aur_enabled() -> bool {
+    match &*SETTINGS.lock().unwrap() {
+        Some(settings) => settings.chaotic_aur,
+        None => std::fs::read_to_string(LIVE_PACMAN_CONF)
+            .unwrap_or_default()
+            .lines()
+            .any(|line| line.trim() == "[chaotic-aur]"),
+    }
+}
+
+/// Returns the packages the enabled repositories need, the Chaotic-AUR keyring and mirrorlist.
+pub fn repository_packages() -> Vec<String> {
+    if chaotic_aur_enabled() {
+        manifest::select("chaotic-aur").packages
+    } else {
+        Vec::new()
+    }
+}
+
+/// ### Returns the `pacman.conf` of the target.
+/// The live one is read, its `[options]` are updated and its repositories kept,
+/// except for the toggled and extra ones which are written from the settings.
+pub fn generate() -> String {
+    let live = std::fs::read_to_string(LIVE_PACMAN_CONF).unwrap_or_default();
+    let settings = match &*SETTINGS.lock().unwrap() {
+        Some(settings) => settings.clone(),
+        None => return live,
+    };
+    let replaced: Vec<&str> = TOGGLED_REPOS
+        .iter()
+        .copied()
+        .chain(settings.repositories.iter().map(|r| r.name.as_str()))
+        .collect();
+
+    let mut lines: Vec<String> = Vec::new();
+    if !live.lines().any(|line| line.trim() == "[options]") {
+        lines.push(String::from("[options]"));
+        lines.extend(options_lines(&settings));
+    }
+    let mut section = String::new();
+    for line in live.lines() {
+        let trimmed = line.trim();
+        if let Some(name) = trimmed.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            if section == "options" {
+                lines.extend(options_lines(&settings));
+            }
+            section = name.to_string();
+        }
+        if replaced.contains(&section.as_str()) {
+            continue;
+        }
+        if section == "options" {
+            let key = trimmed.trim_start_matches('#').split('=').next().unwrap_or_default();
+            if ["ParallelDownloads", "Color", "ILoveCandy"].contains(&key.trim()) {
+                continue;
+            }
+        }
+        lines.push(line.to_string());
+    }
+    if section == "options" {
+        lines.extend(options_lines(&settings));
+    }
+
+    if settings.multilib {
+        lines.push(String::from("\n[multilib]\nInclude = /etc/pacman.d/mirrorlist"));
+    }
+    if settings.chaotic_aur {
+        lines.push(String::from(
+            "\n[chaotic-aur]\nInclude = /etc/pacman.d/chaotic-mirrorlist",
+        ));
+    }
+    for repo in &settings.repositories {
+        lines.push(format!("\n[{}]", repo.name));
+        if let Some(sig_level) = &repo.sig_level {
+            lines.push(format!("SigLevel = {sig_level}"));
+        }
+        lines.push(format!("Server = {}", repo.server));
+    }
+    lines.join("\n") + "\n"
+}
+
+fn options_lines(settings: &PacmanSettings) -> Vec<String> {
+    let mut lines = vec![format!("ParallelDownloads = {}", settings.parallel_downloads)];
+    if settings.color {
+        lines.push(String::from("Color"));
+    }
+    if settings.ilovecandy {
+        lines.push(String::from("ILoveCandy"));
+    }
+    lines
+}
+
+/// Writes the generated `pacman.conf` to the target.
+pub fn write_pacman_conf() -> Result<()> {
+    files_eval(
+        files::write_file(TARGET_PACMAN_CONF, &generate()),
+        "Write pacman.conf",
+    )
+}
+
+/// ### Writes the generated `pacman.conf` for the live system to use, see [`install_conf`].
+/// Packages of the toggled and extra repositories can then be checked and installed
+/// before the target has its own `pacman.conf`. Nothing is written without settings.
+pub fn write_install_conf() -> Result<()> {
+    if SETTINGS.lock().unwrap().is_none() {
+        return Ok(());
+    }
+    files_eval(
+        files::write_file(INSTALL_PACMAN_CONF, &without_missing_includes(&generate())),
+        "Write pacman.conf of the installation",
+    )
+}
+
+/// The `pacman.conf` written by [`write_install_conf`], if any.
+pub fn install_conf() -> Option<&'static str> {
+    let written = SETTINGS.lock().unwrap().is_some()
+        && std::path::Path::new(INSTALL_PACMAN_CONF).exists();
+    written.then_some(INSTALL_PACMAN_CONF)
+}
+
+/// ### Leaves out the repositories including a file the live system doesn't have.
+/// e.g. the Chaotic-AUR mirrorlist, which only comes with the repository's own package.
+fn without_missing_includes(conf: &str) -> String {
+    let mut sections: Vec<(String, Vec<&str>)> = vec![(String::new(), Vec::new())];
+    for line in conf.lines() {
+        let trimmed = line.trim();
+        if let Some(name) = trimmed.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            sections.push((name.to_string(), Vec::new()));
+        }
+        sections.last_mut().unwrap().1.push(line);
+    }
+    let mut lines: Vec<&str> = Vec::new();
+    for (name, section) in sections {
+        let missing = section.iter().find_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            let value = value.trim();
+            (key.trim() == "Include" && !std::path::Path::new(value).exists()).then_some(value)
+        });
+        match missing {
+            Some(include) if name != "options" => log::warn!(
+                "Repository {} is not used during the installation, the live system has no {}",
+                name,
+                include
+            ),
+            _ => lines.extend(section),
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+/// ### Imports the signing keys of the extra repositories in the target keyring.
+/// A key file is copied, added with `pacman-key --add` and removed, other keys are received from
+/// the keyserver with `pacman-key --recv-keys`. Every key is then locally signed.
+pub fn import_repository_keys() -> Result<()> {
+    let repositories = match &*SETTINGS.lock().unwrap() {
+        Some(settings) => settings.repositories.clone(),
+        None => return Ok(()),
+    };
+    for repo in repositories {
+        let key = match &repo.key {
+            Some(key) => key.clone(),
+            None => {
+                if repo.key_file.is_some() {
+                    log::warn!(
+                        "Repository {} has a key file but no key ID, it will not be trusted",
+                        repo.name
+                    );
+                }
+                continue;
+            }
+        };
+        match &repo.key_file {
+            Some(key_file) => {
+                let chroot_path = format!("/tmp/{}.key", repo.name);
+                files::copy_file(&key_file.to_string_lossy(), &format!("/mnt{chroot_path}"))?;
+                let added = exec_eval(
+                    exec_chroot("pacman-key", vec![String::from("--add"), chroot_path.clone()]),
+                    format!("Add signing key of repository {}", repo.name).as_str(),
+                );
+                files_eval(
+                    files::remove_file(&format!("/mnt{chroot_path}")),
+                    format!("Remove signing key file of repository {}", repo.name).as_str(),
+                )?;
+                added?;
+            }
+            None => exec_eval(
+                exec_chroot("pacman-key", vec![String::from("--recv-keys"), key.clone()]),
+                format!("Receive signing key {} of repository {}", key, repo.name).as_str(),
+            )?,
+        }
+        exec_eval(
+            exec_chroot("pacman-key", vec![String::from("--lsign-key"), key.clone()]),
+            format!("Locally sign key {} of repository {}", key, repo.name).as_str(),
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repositories_with_a_missing_include_are_left_out() {
+        let conf = "[options]\nParallelDownloads = 5\n\n[core]\nInclude = /dev/null\n\n[chaotic-aur]\nInclude = /nonexistent/chaotic-mirrorlist\n\n[myrepo]\nServer = https://repo.example.org/$arch\n";
+        assert_eq!(
+            without_missing_includes(conf),
+            "[options]\nParallelDownloads = 5\n\n[core]\nInclude = /dev/null\n\n[myrepo]\nServer = https://repo.example.org/$arch\n"
+        );
+    }
+}
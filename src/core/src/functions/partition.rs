@@ -1,13 +1,61 @@
 // To be reviewed
 
-use crate::args;
-use crate::args::PartitionMode;
+use crate::types;
+use crate::types::PartitionMode;
 use crate::internal::exec::*;
 use crate::internal::*;
+use serde::Serialize;
 use std::path::{Path, PathBuf};
 use log;
 
-pub fn fmt_mount(mountpoint: &str, filesystem: &str, blockdevice: &str) {
+/// A disk the system can be installed to.
+#[derive(Debug, Clone, Serialize)]
+pub struct Disk {
+    /// e.g. `/dev/sda`
+    pub path: PathBuf,
+    /// In bytes
+    pub size: u64,
+    pub model: Option<String>,
+    pub removable: bool,
+}
+
+/// ### Lists the disks from `/sys/block`, sorted by name.
+/// Only devices backed by hardware are kept, loop devices, zram and optical drives are left out.
+pub fn list_disks() -> Vec<Disk> {
+    let mut disks: Vec<Disk> = std::fs::read_dir("/sys/block")
+        .map(|entries| entries.flatten().map(|entry| entry.path()).collect::<Vec<_>>())
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|path| read_disk(path))
+        .collect();
+    disks.sort_by(|a, b| a.path.cmp(&b.path));
+    disks
+}
+
+fn read_disk(path: &Path) -> Option<Disk> {
+    let name = path.file_name()?.to_string_lossy().to_string();
+    if !path.join("device").exists() || name.starts_with("sr") {
+        return None;
+    }
+    let read = |file: &str| {
+        std::fs::read_to_string(path.join(file))
+            .ok()
+            .map(|value| value.trim().to_string())
+    };
+    // Always counted in 512 bytes sectors
+    let sectors: u64 = read("size")?.parse().ok()?;
+    if sectors == 0 {
+        return None;
+    }
+    Some(Disk {
+        path: PathBuf::from("/dev").join(&name),
+        size: sectors * 512,
+        model: read("device/model").filter(|model| !model.is_empty()),
+        removable: read("removable").as_deref() == Some("1"),
+    })
+}
+
+pub fn fmt_mount(mountpoint: &str, filesystem: &str, blockdevice: &str) -> Result<()> {
     let fs_command = match filesystem {
         "ext4" => ("mkfs.ext4", vec![String::from(blockdevice)]),
         "fat32" => ("mkfs.fat", vec![String::from("-F32"), String::from(blockdevice)]),
@@ -15,60 +63,59 @@ pub fn fmt_mount(mountpoint: &str, filesystem: &str, blockdevice: &str) {
         "xfs" => ("mkfs.xfs", vec![String::from(blockdevice)]),
         "noformat" | "don't format" => {
             log::debug!("Not formatting {}", blockdevice);
-            return;
+            return Ok(());
         }
         _ => {
-            crash(
-                format!("Unknown filesystem {filesystem}, used in partition {blockdevice}"),
-                1,
-            );
+            return Err(InstallError::invalid(format!(
+                "Unknown filesystem {filesystem}, used in partition {blockdevice}"
+            )));
         }
     };
 
     exec_eval(
         exec(fs_command.0, fs_command.1),
         format!("Formatting {blockdevice} as {filesystem}").as_str(),
-    );
+    )?;
 
     exec_eval(
         exec("mkdir", vec![String::from("-p"), String::from(mountpoint)]),
         format!("Creating mountpoint {mountpoint} for {blockdevice}").as_str(),
-    );
-    mount(blockdevice, mountpoint, "");
+    )?;
+    mount(blockdevice, mountpoint, "")
 }
 
 pub fn partition(
     device: PathBuf,
     mode: PartitionMode,
     efi: bool,
-    partitions: &mut Vec<args::Partition>,
-) {
-    println!("{:?}", mode);
+    partitions: &mut Vec<types::Partition>,
+) -> Result<()> {
+    log::debug!("Partitioning mode : {:?}", mode);
     match mode {
         PartitionMode::Auto => {
             if !device.exists() {
-                crash(format!("The device {device:?} doesn't exist"), 1);
+                return Err(InstallError::MissingDevice(device));
             }
             log::debug!("automatically partitioning {device:?}");
-            partition_with_efi(&device);
+            partition_with_efi(&device)?;
             
             if device.to_string_lossy().contains("nvme")
                 || device.to_string_lossy().contains("mmcblk")
             {
-                part_nvme(&device, efi);
+                part_nvme(&device, efi)?;
             } else {
-                part_disk(&device, efi);
+                part_disk(&device, efi)?;
             }
         }
         PartitionMode::Manual => {
             log::debug!("Manual partitioning");
-            partitions.sort_by(|a, b| a.mountpoint.len().cmp(&b.mountpoint.len()));
+            partitions.sort_by_key(|a| a.mountpoint.len());
             for partition in partitions {
                 fmt_mount(
                     &partition.mountpoint,
                     &partition.filesystem,
                     &partition.blockdevice,
-                );
+                )?;
                 if &partition.mountpoint == "/boot/efi" {
                     exec_eval(
                         exec(
@@ -83,15 +130,16 @@ pub fn partition(
                             ],
                         ),
                         "set EFI partition as ESP",
-                    );
+                    )?;
                 }
             }
         }
     }
+    Ok(())
 }
 
 
-fn partition_with_efi(device: &Path) {
+fn partition_with_efi(device: &Path) -> Result<()> {
     let device = device.to_string_lossy().to_string();
     exec_eval(
         exec(
@@ -104,7 +152,7 @@ fn partition_with_efi(device: &Path) {
             ],
         ),
         format!("create gpt label on {}", &device).as_str(),
-    );
+    )?;
     exec_eval(
         exec(
             "parted",
@@ -118,7 +166,7 @@ fn partition_with_efi(device: &Path) {
             ],
         ),
         "create EFI partition",
-    );
+    )?;
     exec_eval(
         exec(
             "parted",
@@ -132,7 +180,7 @@ fn partition_with_efi(device: &Path) {
             ],
         ),
         "set EFI partition as ESP",
-    );
+    )?;
     exec_eval(
         exec(
             "parted",
@@ -147,10 +195,10 @@ fn partition_with_efi(device: &Path) {
             ],
         ),
         "create ext4 root partition",
-    );
+    )
 }
 
-fn part_nvme(device: &Path, efi: bool) {
+fn part_nvme(device: &Path, efi: bool) -> Result<()> {
     let device = device.to_string_lossy().to_string();
     if efi {
         exec_eval(
@@ -159,21 +207,21 @@ fn part_nvme(device: &Path, efi: bool) {
                 vec![String::from("-F32"), format!("{}p1", device)],
             ),
             format!("format {}p1 as fat32", device).as_str(),
-        );
+        )?;
         exec_eval(
             exec(
                 "mkfs.ext4",
                 vec![format!("{}p2", device)],
             ),
             format!("format {}p2 as ext4", device).as_str(),
-        );
-        mount(format!("{}p2", device).as_str(), "/mnt", "");
-        files_eval(files::create_directory("/mnt/boot"), "create /mnt/boot");
+        )?;
+        mount(format!("{}p2", device).as_str(), "/mnt", "")?;
+        files_eval(files::create_directory("/mnt/boot"), "create /mnt/boot")?;
         files_eval(
             files::create_directory("/mnt/boot/efi"),
             "create /mnt/boot/efi",
-        );
-        mount(format!("{}p1", device).as_str(), "/mnt/boot/efi", "");
+        )?;
+        mount(format!("{}p1", device).as_str(), "/mnt/boot/efi", "")?;
         exec_eval(
         exec(
             "parted",
@@ -187,28 +235,29 @@ fn part_nvme(device: &Path, efi: bool) {
             ],
         ),
         "set EFI partition as ESP",
-    );
+    )?;
     } else if !efi {
         exec_eval(
             exec("mkfs.ext4", vec![format!("{}p1", device)]),
             format!("format {}p1 as ext4", device).as_str(),
-        );
+        )?;
         exec_eval(
             exec(
                 "mkfs.ext4",
                 vec![format!("{}p2", device)],
             ),
             format!("format {}p2 as ext4", device).as_str(),
-        );
-        mount(format!("{}p2", device).as_str(), "/mnt/", "");
-        files_eval(files::create_directory("/mnt/boot"), "create /mnt/boot");
-        mount(format!("{}p1", device).as_str(), "/mnt/boot", "");
+        )?;
+        mount(format!("{}p2", device).as_str(), "/mnt/", "")?;
+        files_eval(files::create_directory("/mnt/boot"), "create /mnt/boot")?;
+        mount(format!("{}p1", device).as_str(), "/mnt/boot", "")?;
     } else {
-        crash("NVMe devices must be partitioned with EFI", 1);
+        return Err(InstallError::invalid("NVMe devices must be partitioned with EFI"));
     }
+    Ok(())
 }
 
-fn part_disk(device: &Path, efi: bool) {
+fn part_disk(device: &Path, efi: bool) -> Result<()> {
     let device = device.to_string_lossy().to_string();
     if efi {
         exec_eval(
@@ -217,18 +266,18 @@ fn part_disk(device: &Path, efi: bool) {
                 vec![String::from("-F32"), format!("{}1", device)],
             ),
             format!("format {}1 as fat32", device).as_str(),
-        );
+        )?;
         exec_eval(
             exec("mkfs.ext4", vec![format!("{}2", device)]),
             format!("format {}2 as ext4", device).as_str(),
-        );
-        mount(format!("{}2", device).as_str(), "/mnt", "");
-        files_eval(files::create_directory("/mnt/boot"), "create /mnt/boot");
+        )?;
+        mount(format!("{}2", device).as_str(), "/mnt", "")?;
+        files_eval(files::create_directory("/mnt/boot"), "create /mnt/boot")?;
         files_eval(
             files::create_directory("/mnt/boot/efi"),
             "create /mnt/boot/efi",
-        );
-        mount(format!("{}1", device).as_str(), "/mnt/boot/efi", "");
+        )?;
+        mount(format!("{}1", device).as_str(), "/mnt/boot/efi", "")?;
         exec_eval(
             exec(
                 "parted",
@@ -242,28 +291,29 @@ fn part_disk(device: &Path, efi: bool) {
                 ],
             ),
             "set EFI partition as ESP",
-        );
+        )?;
     } else if !efi {
         exec_eval(
             exec("mkfs.ext4", vec![format!("{}1", device)]),
             format!("format {}1 as ext4", device).as_str(),
-        );
+        )?;
         exec_eval(
             exec("mkfs.ext4", vec![format!("{}2", device)]),
             format!("format {}2 as ext4", device).as_str(),
-        );
-        mount(format!("{}2", device).as_str(), "/mnt/", "");
+        )?;
+        mount(format!("{}2", device).as_str(), "/mnt/", "")?;
         files_eval(
             files::create_directory("/mnt/boot"),
             "create directory /mnt/boot",
-        );
-        mount(format!("{}1", device).as_str(), "/mnt/boot", "");
+        )?;
+        mount(format!("{}1", device).as_str(), "/mnt/boot", "")?;
     } else {
-        crash("Disk devices must be partitioned with EFI", 1);
+        return Err(InstallError::invalid("Disk devices must be partitioned with EFI"));
     }
+    Ok(())
 }
 
-pub fn mount(partition: &str, mountpoint: &str, options: &str) {
+pub fn mount(partition: &str, mountpoint: &str, options: &str) -> Result<()> {
     if !options.is_empty() {
         exec_eval(
             exec(
@@ -280,7 +330,7 @@ pub fn mount(partition: &str, mountpoint: &str, options: &str) {
                 partition, options, mountpoint
             )
             .as_str(),
-        );
+        )?;
     } else {
         exec_eval(
             exec(
@@ -288,8 +338,9 @@ pub fn mount(partition: &str, mountpoint: &str, options: &str) {
                 vec![String::from(partition), String::from(mountpoint)],
             ),
             format!("mount {} with no options at {}", partition, mountpoint).as_str(),
-        );
+        )?;
     }
+    Ok(())
 }
 
 pub fn umount(mountpoint: &str) {
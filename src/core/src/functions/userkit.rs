@@ -0,0 +1,47 @@
+use crate::types::UserKit;
+use crate::internal::*;
+use crate::internal::manifest;
+
+pub fn install_userkit(kit: UserKit) -> Result<()> {
+    log::debug!("Installing {:?}", kit);
+
+    match kit {
+        UserKit::Developer => install_dev(),
+        UserKit::Hacker => install_hacks(),
+        UserKit::Artist => install_artist(),
+        UserKit::Entertainment => install_entertainment(),
+        UserKit::Office => install_office(),
+    }
+}
+
+/// Returns the packages of a user kit without installing them.
+pub fn userkit_packages(kit: UserKit) -> Vec<String> {
+    let set = match kit {
+        UserKit::Developer => "kit-developer",
+        UserKit::Hacker => "kit-hacker",
+        UserKit::Artist => "kit-artist",
+        UserKit::Entertainment => "kit-entertainment",
+        UserKit::Office => "kit-office",
+    };
+    manifest::select(set).packages
+}
+
+fn install_dev() -> Result<()> {
+    install::install_set("kit-developer", false)
+}
+
+fn install_hacks() -> Result<()> {
+    install::install_set("kit-hacker", false)
+}
+
+fn install_artist() -> Result<()> {
+    install::install_set("kit-artist", false)
+}
+
+fn install_office() -> Result<()> {
+    install::install_set("kit-office", false)
+}
+
+fn install_entertainment() -> Result<()> {
+    install::install_set("kit-entertainment", false)
+}
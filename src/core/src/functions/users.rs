@@ -0,0 +1,164 @@
+use crate::internal::exec::*;
+use crate::internal::*;
+
+pub fn new_user(username: &str, hasroot: bool, password: &str, do_hash_pass: bool, shell: &str) -> Result<()> {
+    let shell: &str = shell;
+    if do_hash_pass {
+        let _password = hash_pass(password)?;
+    }
+    install::install(vec![String::from(shell_package(shell))], true)?;
+    let shell_path = match shell {
+        "bash" => "/bin/bash",
+        "csh" => "/usr/bin/csh",
+        "fish" => "/usr/bin/fish",
+        "tcsh" => "/usr/bin/tcsh",
+        "zsh" => "/usr/bin/zsh",
+        &_ => "/usr/bin/bash",
+    };
+    exec_eval(
+        exec_chroot(
+            "useradd",
+            vec![
+                String::from("-m"),
+                String::from("-s"),
+                String::from(shell_path),
+                String::from("-p"),
+                String::from(password).replace('\n', ""),
+                String::from(username),
+            ],
+        ),
+        format!("Create user {}", username).as_str(),
+    )?;
+    if hasroot {
+        exec_eval(
+            exec_chroot(
+                "usermod",
+                vec![
+                    String::from("-aG"),
+                    String::from("wheel"),
+                    String::from(username),
+                ],
+            ),
+            format!("Add user {} to wheel group", username).as_str(),
+        )?;
+        files_eval(
+            files::sed_file(
+                "/mnt/etc/sudoers",
+                "# %wheel ALL=(ALL:ALL) ALL",
+                "%wheel ALL=(ALL:ALL) ALL",
+            ),
+            "Add wheel group to sudoers",
+        )?;
+        files_eval(
+            files::append_file("/mnt/etc/sudoers", "\nDefaults pwfeedback\n"),
+            "Add pwfeedback to sudoers",
+        )?;
+        files_eval(
+            files::create_directory("/mnt/var/lib/AccountsService/users/"),
+            "Create /mnt/var/lib/AcountsService",
+        )?;
+        files::create_file(&format!("/mnt/var/lib/AccountsService/users/{}", username))?;
+        files_eval(
+            files::append_file(
+                &format!("/mnt/var/lib/AccountsService/users/{}", username),
+                "[User]\nSession=plasma",
+            ),
+            format!("Populate AccountsService user file for {}", username).as_str(),
+        )?;
+    }
+    Ok(())
+}
+
+/// Returns the package providing `shell`, bash for unknown shells.
+pub fn shell_package(shell: &str) -> &'static str {
+    match shell {
+        "bash" => "bash",
+        "csh" => "tcsh",
+        "fish" => "fish",
+        "tcsh" => "tcsh",
+        "zsh" => "zsh",
+        &_ => "bash",
+    }
+}
+
+/// ### Hashes `password` with openssl as SHA-512 crypt (`$6$`), the format of `/etc/shadow`.
+/// It is read from stdin so that it never shows up in the process list.
+pub fn hash_pass(password: &str) -> Result<String> {
+    let output = Cmd::new("openssl")
+        .args(["passwd", "-6", "-stdin"])
+        .stdin(format!("{password}\n"))
+        .output()
+        .map_err(|e| InstallError::io("Hash password", e))?;
+    if !output.status.success() {
+        return Err(InstallError::Command {
+            context: String::from("Hash password"),
+            code: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Sets the already hashed password of root, passed to `chpasswd` on stdin.
+pub fn root_pass(root_pass: &str) -> Result<()> {
+    exec_eval(
+        Cmd::chroot("chpasswd")
+            .arg("-e")
+            .stdin(format!("root:{}\n", root_pass.trim()))
+            .status_with_retry(),
+        "set root password",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::internal::runner::tests::{chroot, record};
+    use crate::internal::runner::Operation;
+    use std::path::PathBuf;
+
+    #[test]
+    fn root_password_is_passed_on_stdin() {
+        let recorder = record(|| root_pass("$6$salt$hash\n").unwrap());
+        recorder.assert_operations(&[chroot(&["chpasswd", "-e"], Some("root:$6$salt$hash\n"))]);
+    }
+
+    #[test]
+    fn sudo_user_is_created_then_added_to_wheel() {
+        let recorder = record(|| {
+            install::mark_installed(vec![String::from("zsh")]);
+            new_user("alice", true, "$6$salt$hash", false, "zsh").unwrap()
+        });
+        let accounts = PathBuf::from("/mnt/var/lib/AccountsService/users/alice");
+        recorder.assert_operations(&[
+            chroot(
+                &[
+                    "useradd",
+                    "-m",
+                    "-s",
+                    "/usr/bin/zsh",
+                    "-p",
+                    "$6$salt$hash",
+                    "alice",
+                ],
+                None,
+            ),
+            chroot(&["usermod", "-aG", "wheel", "alice"], None),
+            Operation::ReplaceInFile {
+                path: PathBuf::from("/mnt/etc/sudoers"),
+                find: String::from("# %wheel ALL=(ALL:ALL) ALL"),
+                replace: String::from("%wheel ALL=(ALL:ALL) ALL"),
+            },
+            Operation::AppendFile {
+                path: PathBuf::from("/mnt/etc/sudoers"),
+                contents: String::from("\nDefaults pwfeedback\n"),
+            },
+            Operation::CreateDir(PathBuf::from("/mnt/var/lib/AccountsService/users/")),
+            Operation::CreateFile(accounts.clone()),
+            Operation::AppendFile {
+                path: accounts,
+                contents: String::from("[User]\nSession=plasma"),
+            },
+        ]);
+    }
+}
@@ -0,0 +1,376 @@
+//! Runs an [`InstallPlan`] step by step, reporting progress to the front-end.
+
+use crate::events::{self, Event};
+use crate::functions::hardware::Firmware;
+use crate::functions::*;
+use crate::internal::packages::PackagePlan;
+use crate::internal::*;
+use crate::plan::{InstallPlan, Settings};
+use crate::types::BaseSource;
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A step of the installation, in the order they run.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Step {
+    Partition,
+    /// Base packages, keyring, flatpak and fstab, every planned package is installed here
+    Base,
+    Bootloader,
+    Locale,
+    Network,
+    Desktop,
+    Laptop,
+    Users,
+    LiveConfig,
+    Nvidia,
+    Swap,
+    Kits,
+    Initramfs,
+    /// Finishes an offline installation
+    Finish,
+}
+
+/// ### Installs AxOS following an [`InstallPlan`].
+/// The installer relies on global settings (retry policy, chroot runner, package sources),
+/// so only one should run per process at a time.
+///
+/// ```ignore
+/// let plan = InstallPlan::from_file("/tmp/config.json")?;
+/// Installer::new(plan)
+///     .on_progress(|event| println!("{:?}", event))
+///     .run()?;
+/// ```
+pub struct Installer {
+    plan: InstallPlan,
+    packages: Option<PackagePlan>,
+    cancelled: Arc<AtomicBool>,
+}
+
+/// Cancels an installation from another thread, see [`Installer::canceller`].
+#[derive(Clone)]
+pub struct Canceller(Arc<AtomicBool>);
+
+impl Canceller {
+    /// Stops the installation once the running step is done, it then fails with [`InstallError::Cancelled`].
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Installer {
+    /// The passwords of the plan are redacted from the log from now on, see [`output::redact`].
+    pub fn new(plan: InstallPlan) -> Self {
+        for user in &plan.users {
+            output::add_secret(&user.password);
+        }
+        output::add_secret(&plan.root_password);
+        Installer {
+            plan,
+            packages: None,
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Calls `callback` with every [`Event`] of the installation, see [`events::set_listener`].
+    pub fn on_progress<F: FnMut(&Event) + Send + 'static>(self, callback: F) -> Self {
+        events::set_listener(callback);
+        self
+    }
+
+    pub fn plan(&self) -> &InstallPlan {
+        &self.plan
+    }
+
+    pub fn canceller(&self) -> Canceller {
+        Canceller(self.cancelled.clone())
+    }
+
+    /// The steps the plan enables, in the order [`Installer::run`] runs them.
+    pub fn steps(&self) -> Vec<Step> {
+        let plan = &self.plan;
+        let mut steps = vec![
+            Step::Partition,
+            Step::Base,
+            Step::Bootloader,
+            Step::Locale,
+            Step::Network,
+        ];
+        if plan.desktop.is_some() {
+            steps.push(Step::Desktop);
+        }
+        steps.extend([Step::Laptop, Step::Users, Step::LiveConfig]);
+        if plan.nvidia.is_some() {
+            steps.push(Step::Nvidia);
+        }
+        if plan.swap > 0 {
+            steps.push(Step::Swap);
+        }
+        if !plan.kits.is_empty() {
+            steps.push(Step::Kits);
+        }
+        steps.push(Step::Initramfs);
+        if plan.settings.offline.is_some() {
+            steps.push(Step::Finish);
+        }
+        steps
+    }
+
+    /// ### Applies the settings and runs the pre-flight package check, without touching the disk.
+    /// Fails if a required package cannot be installed.
+    pub fn check(&mut self) -> Result<()> {
+        apply_settings(&self.plan.settings)?;
+        // Kernels are validated while planning, against the repositories of these settings
+        preflight::sync_databases()?;
+        let mut packages = plan_packages(&self.plan)?;
+        log::info!("Planned packages : {:?}", packages.required());
+        log::info!("Planned optional packages : {:?}", packages.optional());
+        let report = packages.check()?;
+        report.log();
+        if !report.is_ok() {
+            return Err(InstallError::Packages(String::from(
+                "Pre-flight package check failed, nothing was written to disk",
+            )));
+        }
+        self.packages = Some(packages);
+        Ok(())
+    }
+
+    /// Downloads every planned package into the package cache, checking them first if needed.
+    pub fn prefetch(&mut self) -> Result<()> {
+        if self.packages.is_none() {
+            self.check()?;
+        }
+        match &self.packages {
+            Some(packages) => packages.prefetch(),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs the pre-flight check, then every step of [`Installer::steps`].
+    pub fn run(&mut self) -> Result<()> {
+        self.check()?;
+        if self.plan.settings.prefetch {
+            self.prefetch()?;
+        }
+        let result = self.run_steps();
+        output::set_step(None);
+        result
+    }
+
+    fn run_steps(&mut self) -> Result<()> {
+        let steps = self.steps();
+        let total = steps.len();
+        for (i, step) in steps.into_iter().enumerate() {
+            let index = i + 1;
+            // Steps are not interrupted, the target would be left half configured
+            if self.cancelled.load(Ordering::Relaxed) {
+                return Err(InstallError::Cancelled);
+            }
+            events::emit(Event::StepStarted {
+                step,
+                index,
+                total,
+                percent: events::percent(i, total),
+            });
+            output::set_step(Some(step));
+            if let Err(error) = self.run_step(step) {
+                events::emit(Event::StepFailed {
+                    step,
+                    error: error.to_string(),
+                });
+                return Err(error);
+            }
+            events::emit(Event::StepFinished {
+                step,
+                index,
+                total,
+                percent: events::percent(index, total),
+            });
+        }
+        Ok(())
+    }
+
+    /// ### Runs a single step, the steps before it must already have run.
+    /// Step events are only emitted by [`Installer::run`].
+    pub fn run_step(&mut self, step: Step) -> Result<()> {
+        let plan = &self.plan;
+        match step {
+            Step::Partition => {
+                let partitioning = &plan.partitioning;
+                log::info!("Block device to use : {:?}", partitioning.device);
+                log::info!("Partitioning mode : {:?}", partitioning.mode);
+                log::info!("Partitioning for EFI : {}", partitioning.efi);
+                partition::partition(
+                    partitioning.device.clone(),
+                    partitioning.mode,
+                    partitioning.efi,
+                    &mut partitioning.partitions.clone(),
+                )?;
+            }
+            Step::Base => {
+                log::info!("Base system source : {:?}", plan.base.source);
+                if plan.base.source == BaseSource::Live {
+                    live::copy_live_system()?;
+                }
+                log::info!("Kernels to install : {:?}", plan.base.kernels);
+                log::info!("Microcode : {:?}", plan.base.microcode);
+                if self.packages.is_none() {
+                    self.check()?;
+                }
+                // Every package of the enabled steps goes in at once, the steps after only configure
+                if let Some(packages) = self.packages.take() {
+                    packages.install()?;
+                }
+                let plan = &self.plan;
+                base::install_base_packages(plan.base.kernels.clone(), plan.base.microcode)?;
+                base::setup_archlinux_keyring()?;
+                if plan.base.flatpak {
+                    base::install_flatpak()?;
+                }
+                base::genfstab()?;
+            }
+            Step::Bootloader => {
+                let bootloader = &plan.bootloader;
+                log::info!("Installing bootloader to : {:?}", bootloader.efidir);
+                log::info!("Default kernel : {:?}", bootloader.default_kernel);
+                base::install_bootloader_efi(
+                    bootloader.efidir.clone(),
+                    bootloader.default_kernel.clone(),
+                )?;
+            }
+            Step::Locale => {
+                log::info!("Adding Locales : {:?}", plan.locale.locales);
+                log::info!("Using keymap : {}", plan.locale.keymap);
+                log::info!("Setting timezone : {}", plan.locale.timezone);
+                locale::set_locale(plan.locale.locales.join(" "))?;
+                locale::set_keyboard(&plan.locale.keymap)?;
+                locale::set_timezone(&plan.locale.timezone)?;
+            }
+            Step::Network => {
+                log::info!("Hostname : {}", plan.network.hostname);
+                log::info!("Enabling ipv6 : {}", plan.network.ipv6);
+                network::set_hostname(&plan.network.hostname)?;
+                network::create_hosts()?;
+                if plan.network.ipv6 {
+                    network::enable_ipv6()?;
+                }
+            }
+            Step::Desktop => {
+                log::info!("Installing desktop : {:?}", plan.desktop);
+                if let Some(desktop) = plan.desktop {
+                    desktops::install_desktop_setup(desktop)?;
+                }
+            }
+            Step::Laptop => {
+                log::info!("Laptop power daemon : {:?}", plan.power_daemon);
+                laptop::setup_laptop(plan.power_daemon)?;
+            }
+            Step::Users => {
+                for user in &plan.users {
+                    log::info!("Creating user : {}", user.name);
+                    log::info!("Enabling root for user : {}", user.hasroot);
+                    log::info!("Setting user shell : {}", user.shell);
+                    users::new_user(&user.name, user.hasroot, &user.password, false, &user.shell)?;
+                }
+                log::info!("Setting root password");
+                users::root_pass(&plan.root_password)?;
+            }
+            Step::LiveConfig => {
+                log::info!("Copying live config");
+                base::copy_live_config()?;
+            }
+            Step::Nvidia => {
+                log::info!("Graphics mode : {:?}", plan.nvidia);
+                if let Some(mode) = plan.nvidia {
+                    base::install_nvidia(mode)?;
+                }
+            }
+            Step::Swap => {
+                log::info!("Enabling swap: {}M ", plan.swap);
+                base::enable_swap(plan.swap)?;
+            }
+            Step::Kits => {
+                log::info!("Installing user kits : {:?}", plan.kits);
+                for kit in &plan.kits {
+                    userkit::install_userkit(*kit)?;
+                }
+            }
+            Step::Initramfs => {
+                log::info!("Initramfs generator : {:?}", plan.initramfs.generator);
+                for hook in &plan.initramfs.hooks {
+                    initramfs::require_hook(hook);
+                }
+                for module in &plan.initramfs.modules {
+                    initramfs::require_module(module);
+                }
+                initramfs::regenerate(plan.initramfs.generator)?;
+            }
+            Step::Finish => {
+                if let Some(offline) = &plan.settings.offline {
+                    log::info!("Restoring online pacman.conf : {}", offline.restore_online);
+                    offline::finish(offline.restore_online)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sets up the package sources and caches the rest of the installation relies on.
+fn apply_settings(settings: &Settings) -> Result<()> {
+    retry::set_policy(settings.retry);
+    exec::set_chroot_runner(settings.chroot);
+    if let Some(pacman) = &settings.pacman {
+        pacman::configure(pacman.clone());
+        pacman::write_install_conf()?;
+    }
+    if let Some(offline) = &settings.offline {
+        offline::enable(&offline.repository)?;
+    }
+    match &settings.package_cache {
+        Some(dir) => cache::set_cache_dir(dir)?,
+        None if settings.use_prefetched => cache::use_prefetched()?,
+        None => {}
+    }
+    Ok(())
+}
+
+/// ### Gathers the packages of every step enabled in the plan.
+/// Required packages are installed in one transaction, user kits and extra packages
+/// in a second, non-fatal one.
+fn plan_packages(plan: &InstallPlan) -> Result<PackagePlan> {
+    let mut packages = PackagePlan::new();
+    packages.require(
+        "base",
+        base::base_packages(plan.base.kernels.clone(), plan.base.microcode)?,
+    );
+    if plan.base.flatpak {
+        packages.require("flatpak", base::flatpak_packages());
+    }
+    packages.require("bootloader", base::bootloader_packages(Firmware::Uefi));
+    if let Some(desktop) = plan.desktop {
+        packages.require("desktop", desktops::desktop_packages(desktop));
+    }
+    packages.require("laptop", laptop::laptop_packages(plan.power_daemon));
+    for user in &plan.users {
+        packages.require(
+            "users",
+            vec![String::from(users::shell_package(&user.shell))],
+        );
+    }
+    if let Some(mode) = plan.nvidia {
+        packages.require("nvidia", base::nvidia_packages(mode));
+    }
+    packages.require(
+        "initramfs",
+        initramfs::generator_packages(plan.initramfs.generator),
+    );
+    for kit in &plan.kits {
+        packages.want("user kits", userkit::userkit_packages(*kit));
+    }
+    packages.want("extra packages", plan.extra_packages.clone());
+    Ok(packages)
+}
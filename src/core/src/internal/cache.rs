@@ -0,0 +1,72 @@
+use crate::internal::*;
+use lazy_static::lazy_static;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// The cache of the live system, as set in its `pacman.conf`.
+pub const HOST_CACHE: &str = "/var/cache/pacman/pkg";
+
+/// Where a prefetch records the cache it filled, on the live system.
+const PREFETCH_RECORD: &str = "/var/lib/axinstall/prefetch-cache";
+
+lazy_static! {
+    static ref CACHE_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+}
+
+/// ### Downloads packages into `dir` and installs them from there, instead of the target's cache.
+/// `dir` can be the live system's cache ([`HOST_CACHE`]) or a shared directory, e.g. on the
+/// live USB, so that reinstalling or installing several machines reuses the downloads.
+pub fn set_cache_dir(dir: &Path) -> Result<()> {
+    files_eval(
+        files::create_directory(&dir.to_string_lossy()),
+        format!("Create package cache {dir:?}").as_str(),
+    )?;
+    log::info!("Using package cache {:?}", dir);
+    *CACHE_DIR.lock().unwrap() = Some(dir.to_path_buf());
+    Ok(())
+}
+
+pub fn cache_dir() -> Option<PathBuf> {
+    CACHE_DIR.lock().unwrap().clone()
+}
+
+/// Records that the planned packages were prefetched into `dir`, see [`use_prefetched`].
+pub fn record_prefetch(dir: &Path) -> Result<()> {
+    files_eval(
+        files::write_file(PREFETCH_RECORD, &format!("{}\n", dir.to_string_lossy())),
+        "Record the prefetched package cache",
+    )
+}
+
+/// ### Installs from the cache an earlier prefetch filled, when no cache is set.
+/// `plan --prefetch` without a package cache fills the live system's one, a later
+/// run opting in with `use_prefetched` then installs from it instead of downloading everything again.
+/// A prefetch of this run already set the cache it filled.
+pub fn use_prefetched() -> Result<()> {
+    if cache_dir().is_some() {
+        return Ok(());
+    }
+    let dir = match std::fs::read_to_string(PREFETCH_RECORD) {
+        Ok(dir) => PathBuf::from(dir.trim()),
+        Err(_) => {
+            log::warn!(
+                "No prefetch was recorded in {}, downloading packages",
+                PREFETCH_RECORD
+            );
+            return Ok(());
+        }
+    };
+    if !dir.is_dir() {
+        log::warn!(
+            "The prefetched package cache {:?} is gone, downloading packages",
+            dir
+        );
+        return Ok(());
+    }
+    log::info!(
+        "Reusing the package cache {:?} an earlier prefetch filled, as recorded in {}",
+        dir,
+        PREFETCH_RECORD
+    );
+    set_cache_dir(&dir)
+}
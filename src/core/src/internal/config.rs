@@ -0,0 +1,340 @@
+use crate::functions::*;
+use crate::internal::*;
+use crate::plan::{self, InstallPlan};
+use crate::types;
+use crate::types::{
+    BaseSource, DesktopSetup, GraphicsMode, InitramfsGenerator, Microcode, PartitionMode, PowerDaemon,
+    UserKit,
+};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize)]
+struct Config {
+    partition: Partition,
+    bootloader: Bootloader,
+    locale: Locale,
+    networking: Networking,
+    users: Vec<Users>,
+    rootpass: String,
+    desktop: String,
+    swap: u64,
+    nvidia: bool,
+    #[serde(default)]
+    graphics_mode: GraphicsMode,
+    #[serde(default)]
+    power_daemon: PowerDaemon,
+    extra_packages: Vec<String>,
+    kernel: Kernels,
+    #[serde(default)]
+    default_kernel: Option<String>,
+    #[serde(default)]
+    microcode: Microcode,
+    artist_uk: bool,
+    devel_uk: bool,
+    hacker_uk: bool,
+    office_uk: bool,
+    entertainment_uk: bool,
+    flatpak: bool,
+    #[serde(default)]
+    initramfs: Initramfs,
+    #[serde(default)]
+    offline: Option<Offline>,
+    #[serde(default)]
+    base_source: BaseSource,
+    #[serde(default)]
+    pacman: Option<pacman::PacmanSettings>,
+    #[serde(default)]
+    package_cache: Option<PathBuf>,
+    #[serde(default)]
+    prefetch: bool,
+    #[serde(default)]
+    use_prefetched: bool,
+    #[serde(default)]
+    retry: retry::RetryPolicy,
+    #[serde(default)]
+    chroot: exec::ChrootRunner,
+}
+
+/// Either a single kernel (`"linux-zen"`) or a list of them (`["linux-zen", "linux-lts"]`).
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Kernels {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl Kernels {
+    fn to_vec(&self) -> Vec<String> {
+        match self {
+            Kernels::Single(kernel) => vec![kernel.clone()],
+            Kernels::Multiple(kernels) => kernels.clone(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Partition {
+    device: String,
+    mode: PartitionMode,
+    efi: bool,
+    partitions: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Bootloader {
+    r#type: String,
+    location: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Locale {
+    locale: Vec<String>,
+    keymap: String,
+    timezone: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Networking {
+    hostname: String,
+    ipv6: bool,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct Initramfs {
+    #[serde(default)]
+    generator: InitramfsGenerator,
+    #[serde(default)]
+    hooks: Vec<String>,
+    #[serde(default)]
+    modules: Vec<String>,
+}
+
+/// Install from a local repository instead of the mirrors.
+#[derive(Serialize, Deserialize)]
+struct Offline {
+    /// Directory or mounted ISO with the repository database and packages
+    repository: PathBuf,
+    /// Put the online pacman.conf back in the target once installed
+    #[serde(default)]
+    restore_online: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Users {
+    name: String,
+    password: String,
+    hasroot: bool,
+    shell: String,
+}
+
+/// The name of `desktop` in config files, [`parse_desktop`] reads it back.
+fn desktop_name(desktop: Option<DesktopSetup>) -> &'static str {
+    match desktop {
+        Some(DesktopSetup::Kde) => "kde",
+        Some(DesktopSetup::Calla) => "calla",
+        Some(DesktopSetup::Sleex) => "sleex",
+        Some(DesktopSetup::Theom) => "theom",
+        Some(DesktopSetup::None) => "None/DIY",
+        None => "",
+    }
+}
+
+fn parse_desktop(desktop: &str) -> Option<DesktopSetup> {
+    match desktop.to_lowercase().as_str() {
+        "kde" | "plasma" => Some(DesktopSetup::Kde),
+        "calla" => Some(DesktopSetup::Calla),
+        "sleex" => Some(DesktopSetup::Sleex),
+        "theom" => Some(DesktopSetup::Theom),
+        "none/diy" => Some(DesktopSetup::None),
+        _ => None,
+    }
+}
+
+fn parse_config(configpath: &Path) -> Result<Config> {
+    let data = std::fs::read_to_string(configpath)
+        .map_err(|e| InstallError::io(format!("Read config file {configpath:?}"), e))?;
+    log::debug!("[ \x1b[2;1;32mOK\x1b[0m ] Read config file {configpath:?}");
+    let config: Config = serde_json::from_str(&data).map_err(|e| {
+        InstallError::invalid(format!("Parse config file {configpath:?}  ERROR: {}", e))
+    })?;
+    log::debug!("[ \x1b[2;1;32mOK\x1b[0m ] Parse config file {configpath:?}",);
+    Ok(config)
+}
+
+/// Reads a config file into the plan an [`Installer`](crate::Installer) runs.
+pub fn load(configpath: &Path) -> Result<InstallPlan> {
+    into_plan(parse_config(configpath)?)
+}
+
+/// Reads a config already parsed as JSON, e.g. sent by a front-end.
+pub fn from_value(value: serde_json::Value) -> Result<InstallPlan> {
+    let config: Config = serde_json::from_value(value)
+        .map_err(|e| InstallError::invalid(format!("Parse config  ERROR: {}", e)))?;
+    into_plan(config)
+}
+
+fn into_plan(config: Config) -> Result<InstallPlan> {
+    let mut partitions: Vec<types::Partition> = Vec::new();
+    for partition in config.partition.partitions {
+        let fields: Vec<&str> = partition.split(':').collect();
+        if fields.len() < 3 {
+            return Err(InstallError::invalid(format!(
+                "Partition {partition:?} must be given as mountpoint:blockdevice:filesystem"
+            )));
+        }
+        partitions.push(types::Partition::new(
+            fields[0].to_string(),
+            fields[1].to_string(),
+            fields[2].to_string(),
+        ));
+    }
+    let kits = [
+        (config.artist_uk, UserKit::Artist),
+        (config.devel_uk, UserKit::Developer),
+        (config.hacker_uk, UserKit::Hacker),
+        (config.office_uk, UserKit::Office),
+        (config.entertainment_uk, UserKit::Entertainment),
+    ];
+    Ok(InstallPlan {
+        partitioning: plan::Partitioning {
+            device: PathBuf::from("/dev/").join(config.partition.device.as_str()),
+            mode: config.partition.mode,
+            efi: config.partition.efi,
+            partitions,
+        },
+        base: plan::BaseSystem {
+            source: config.base_source,
+            kernels: config.kernel.to_vec(),
+            microcode: config.microcode,
+            flatpak: config.flatpak,
+        },
+        bootloader: plan::Bootloader {
+            efidir: PathBuf::from(config.bootloader.location),
+            default_kernel: config.default_kernel,
+        },
+        locale: plan::Locale {
+            locales: config.locale.locale,
+            keymap: config.locale.keymap,
+            timezone: config.locale.timezone,
+        },
+        network: plan::Network {
+            hostname: config.networking.hostname,
+            ipv6: config.networking.ipv6,
+        },
+        users: config
+            .users
+            .into_iter()
+            .map(|user| plan::User {
+                name: user.name,
+                password: user.password,
+                hasroot: user.hasroot,
+                shell: user.shell,
+            })
+            .collect(),
+        root_password: config.rootpass,
+        desktop: parse_desktop(&config.desktop),
+        power_daemon: config.power_daemon,
+        nvidia: config.nvidia.then_some(config.graphics_mode),
+        swap: config.swap,
+        kits: kits
+            .into_iter()
+            .filter(|(enabled, _)| *enabled)
+            .map(|(_, kit)| kit)
+            .collect(),
+        extra_packages: config.extra_packages,
+        initramfs: plan::Initramfs {
+            generator: config.initramfs.generator,
+            hooks: config.initramfs.hooks,
+            modules: config.initramfs.modules,
+        },
+        settings: plan::Settings {
+            offline: config.offline.map(|offline| plan::Offline {
+                repository: offline.repository,
+                restore_online: offline.restore_online,
+            }),
+            pacman: config.pacman,
+            package_cache: config.package_cache,
+            prefetch: config.prefetch,
+            use_prefetched: config.use_prefetched,
+            retry: config.retry,
+            chroot: config.chroot,
+        },
+    })
+}
+
+/// ### Writes `plan` in the format of config files, so that it can be reused with `axinstall-cli config`.
+/// Only GRUB in EFI mode is supported by plans, the bootloader type is always `grub-efi`.
+pub fn to_value(plan: &InstallPlan) -> serde_json::Value {
+    let device = plan.partitioning.device.to_string_lossy();
+    let has_kit = |kit: UserKit| plan.kits.contains(&kit);
+    let config = Config {
+        partition: Partition {
+            device: device.strip_prefix("/dev/").unwrap_or(&device).to_string(),
+            mode: plan.partitioning.mode,
+            efi: plan.partitioning.efi,
+            partitions: plan
+                .partitioning
+                .partitions
+                .iter()
+                .map(|p| format!("{}:{}:{}", p.mountpoint, p.blockdevice, p.filesystem))
+                .collect(),
+        },
+        bootloader: Bootloader {
+            r#type: String::from("grub-efi"),
+            location: plan.bootloader.efidir.to_string_lossy().to_string(),
+        },
+        locale: Locale {
+            locale: plan.locale.locales.clone(),
+            keymap: plan.locale.keymap.clone(),
+            timezone: plan.locale.timezone.clone(),
+        },
+        networking: Networking {
+            hostname: plan.network.hostname.clone(),
+            ipv6: plan.network.ipv6,
+        },
+        users: plan
+            .users
+            .iter()
+            .map(|user| Users {
+                name: user.name.clone(),
+                password: user.password.clone(),
+                hasroot: user.hasroot,
+                shell: user.shell.clone(),
+            })
+            .collect(),
+        rootpass: plan.root_password.clone(),
+        desktop: desktop_name(plan.desktop).to_string(),
+        swap: plan.swap,
+        nvidia: plan.nvidia.is_some(),
+        graphics_mode: plan.nvidia.unwrap_or_default(),
+        power_daemon: plan.power_daemon,
+        extra_packages: plan.extra_packages.clone(),
+        kernel: Kernels::Multiple(plan.base.kernels.clone()),
+        default_kernel: plan.bootloader.default_kernel.clone(),
+        microcode: plan.base.microcode,
+        artist_uk: has_kit(UserKit::Artist),
+        devel_uk: has_kit(UserKit::Developer),
+        hacker_uk: has_kit(UserKit::Hacker),
+        office_uk: has_kit(UserKit::Office),
+        entertainment_uk: has_kit(UserKit::Entertainment),
+        flatpak: plan.base.flatpak,
+        initramfs: Initramfs {
+            generator: plan.initramfs.generator,
+            hooks: plan.initramfs.hooks.clone(),
+            modules: plan.initramfs.modules.clone(),
+        },
+        offline: plan.settings.offline.as_ref().map(|offline| Offline {
+            repository: offline.repository.clone(),
+            restore_online: offline.restore_online,
+        }),
+        base_source: plan.base.source,
+        pacman: plan.settings.pacman.clone(),
+        package_cache: plan.settings.package_cache.clone(),
+        prefetch: plan.settings.prefetch,
+        use_prefetched: plan.settings.use_prefetched,
+        retry: plan.settings.retry,
+        chroot: plan.settings.chroot,
+    };
+    serde_json::to_value(config).unwrap()
+}
@@ -0,0 +1,91 @@
+use std::fmt;
+use std::path::PathBuf;
+
+/// ### Why the installation stopped.
+/// Every step returns it up to `main`, which unmounts the target and exits with [`InstallError::exit_code`].
+#[derive(Debug)]
+pub enum InstallError {
+    /// A command exited with a non-zero code, or was killed by a signal
+    Command {
+        context: String,
+        code: Option<i32>,
+        stderr: String,
+    },
+    /// A file could not be read or written, or a command could not be started
+    Io {
+        context: String,
+        source: std::io::Error,
+    },
+    /// The config file or an argument is invalid
+    Invalid(String),
+    /// A block device or partition doesn't exist
+    MissingDevice(PathBuf),
+    /// Packages are missing from the repositories or can't be installed together
+    Packages(String),
+    /// The installation was cancelled by the front-end
+    Cancelled,
+}
+
+pub type Result<T> = std::result::Result<T, InstallError>;
+
+impl InstallError {
+    pub fn io<S: Into<String>>(context: S, source: std::io::Error) -> Self {
+        InstallError::Io {
+            context: context.into(),
+            source,
+        }
+    }
+
+    pub fn invalid<S: Into<String>>(message: S) -> Self {
+        InstallError::Invalid(message.into())
+    }
+
+    /// The exit code of `axinstall-cli`, they are listed in the README.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            InstallError::Command { .. } => 1,
+            InstallError::Invalid(_) => 2,
+            InstallError::MissingDevice(_) => 3,
+            InstallError::Packages(_) => 4,
+            InstallError::Io { .. } => 5,
+            InstallError::Cancelled => 6,
+        }
+    }
+}
+
+impl fmt::Display for InstallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InstallError::Command {
+                context,
+                code,
+                stderr,
+            } => {
+                match code {
+                    Some(code) => write!(f, "{}  ERROR: exited with code {}", context, code)?,
+                    None => write!(f, "{}  ERROR: killed by a signal", context)?,
+                }
+                if !stderr.trim().is_empty() {
+                    write!(f, "\n{}", stderr.trim_end())?;
+                }
+                Ok(())
+            }
+            InstallError::Io { context, source } => write!(f, "{}  ERROR: {}", context, source),
+            InstallError::Invalid(message) => write!(f, "{}", message),
+            InstallError::MissingDevice(device) => {
+                write!(f, "The device {} doesn't exist", device.display())
+            }
+            InstallError::Packages(message) => write!(f, "{}", message),
+            InstallError::Cancelled => write!(f, "The installation was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for InstallError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            InstallError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,349 @@
+use crate::events;
+use crate::internal::output::{self, Stream};
+use crate::internal::retry;
+use crate::internal::runner::{self, Operation};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::Mutex;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// The root of the target installation.
+const ROOT: &str = "/mnt";
+
+/// ### How commands are run inside the target system.
+/// - `arch-chroot` mounts the API filesystems and `resolv.conf` for every command.
+/// - `chroot` is the plain chroot, the API filesystems must already be mounted.
+/// - `systemd-nspawn` runs the command in a lightweight container.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChrootRunner {
+    #[default]
+    ArchChroot,
+    Chroot,
+    SystemdNspawn,
+}
+
+lazy_static! {
+    static ref RUNNER: Mutex<ChrootRunner> = Mutex::new(ChrootRunner::default());
+}
+
+pub fn set_chroot_runner(runner: ChrootRunner) {
+    log::debug!("Chroot runner : {:?}", runner);
+    *RUNNER.lock().unwrap() = runner;
+}
+
+/// ### A command, run on the host or inside the target, without going through a shell.
+/// Every argument is passed as is, so spaces, quotes and `$` need no escaping.
+///
+/// ```ignore
+/// Cmd::chroot("chpasswd")
+///     .arg("-e")
+///     .stdin(format!("root:{hash}\n"))
+///     .status()
+/// ```
+#[derive(Debug, Clone)]
+pub struct Cmd {
+    program: String,
+    args: Vec<String>,
+    chroot: bool,
+    stdin: Option<Vec<u8>>,
+    env: Vec<(String, String)>,
+    cwd: Option<PathBuf>,
+    timeout: Option<Duration>,
+}
+
+impl Cmd {
+    /// A command run on the host.
+    pub fn new(program: &str) -> Self {
+        Cmd {
+            program: program.to_string(),
+            args: Vec::new(),
+            chroot: false,
+            stdin: None,
+            env: Vec::new(),
+            cwd: None,
+            timeout: None,
+        }
+    }
+
+    /// A command run inside `/mnt` with the configured [`ChrootRunner`].
+    pub fn chroot(program: &str) -> Self {
+        Cmd {
+            chroot: true,
+            ..Cmd::new(program)
+        }
+    }
+
+    pub fn arg<S: Into<String>>(mut self, arg: S) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Writes `input` to the standard input of the command, then closes it.
+    pub fn stdin<B: Into<Vec<u8>>>(mut self, input: B) -> Self {
+        self.stdin = Some(input.into());
+        self
+    }
+
+    pub fn env(mut self, key: &str, value: &str) -> Self {
+        self.env.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// The working directory, inside the target for chroot commands.
+    #[allow(dead_code)]
+    pub fn current_dir<P: Into<PathBuf>>(mut self, dir: P) -> Self {
+        self.cwd = Some(dir.into());
+        self
+    }
+
+    /// Kills the command if it runs longer than `timeout`, it then fails with [`std::io::ErrorKind::TimedOut`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// ### The full argument vector that is executed, runner included.
+    /// Inside the target the environment and working directory are set with `env`,
+    /// or with the `systemd-nspawn` options, as the runners don't forward them.
+    pub fn argv(&self) -> Vec<String> {
+        let mut argv: Vec<String> = Vec::new();
+        if self.chroot {
+            let runner = *RUNNER.lock().unwrap();
+            match runner {
+                ChrootRunner::ArchChroot => {
+                    argv.extend([String::from("arch-chroot"), ROOT.to_string()])
+                }
+                ChrootRunner::Chroot => argv.extend([String::from("chroot"), ROOT.to_string()]),
+                ChrootRunner::SystemdNspawn => {
+                    argv.extend(["systemd-nspawn", "--quiet", "-D", ROOT].map(String::from));
+                    if let Some(cwd) = &self.cwd {
+                        argv.push(format!("--chdir={}", cwd.to_string_lossy()));
+                    }
+                    argv.extend(self.env.iter().map(|(k, v)| format!("--setenv={k}={v}")));
+                }
+            }
+            if runner != ChrootRunner::SystemdNspawn && (self.cwd.is_some() || !self.env.is_empty())
+            {
+                argv.push(String::from("env"));
+                if let Some(cwd) = &self.cwd {
+                    argv.push(format!("--chdir={}", cwd.to_string_lossy()));
+                }
+                argv.extend(self.env.iter().map(|(k, v)| format!("{k}={v}")));
+            }
+        }
+        argv.push(self.program.clone());
+        argv.extend(self.args.iter().cloned());
+        argv
+    }
+
+    /// The command as seen by the runner.
+    fn operation(&self) -> Operation {
+        Operation::Command {
+            argv: self.argv(),
+            stdin: self
+                .stdin
+                .as_ref()
+                .map(|input| String::from_utf8_lossy(input).to_string()),
+        }
+    }
+
+    fn command(&self) -> Command {
+        let argv = self.argv();
+        let mut command = Command::new(&argv[0]);
+        command.args(&argv[1..]);
+        if !self.chroot {
+            command.envs(self.env.iter().map(|(k, v)| (k, v)));
+            if let Some(cwd) = &self.cwd {
+                command.current_dir(cwd);
+            }
+        }
+        if self.stdin.is_some() {
+            command.stdin(Stdio::piped());
+        }
+        command
+    }
+
+    fn spawn(&self, command: &mut Command) -> std::io::Result<Child> {
+        let mut child = command.spawn()?;
+        if let (Some(input), Some(mut stdin)) = (self.stdin.clone(), child.stdin.take()) {
+            // Written from a thread, a command may not read its input before writing its output
+            std::thread::spawn(move || stdin.write_all(&input));
+        }
+        Ok(child)
+    }
+
+    /// Runs the command once, its output is shown as usual and captured in the install log.
+    pub fn status(&self) -> std::io::Result<ExitStatus> {
+        if !runner::perform(self.operation()) {
+            return Ok(runner::success());
+        }
+        let mut command = self.command();
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let mut child = self.spawn(&mut command)?;
+        let stdout = tee_stdout(&mut child);
+        let stderr = tee_stderr(&mut child, |line| eprintln!("{}", line));
+        let status = wait(&mut child, self.timeout);
+        join(stdout);
+        join(stderr);
+        status
+    }
+
+    /// Runs the command once, capturing its stdout and stderr.
+    pub fn output(&self) -> std::io::Result<std::process::Output> {
+        if !runner::perform(self.operation()) {
+            return Ok(std::process::Output {
+                status: runner::success(),
+                stdout: Vec::new(),
+                stderr: Vec::new(),
+            });
+        }
+        let mut command = self.command();
+        command.stdout(Stdio::piped()).stderr(Stdio::piped());
+        let mut child = self.spawn(&mut command)?;
+        let stdout = read_all(child.stdout.take());
+        let stderr = read_all(child.stderr.take());
+        let status = wait(&mut child, self.timeout)?;
+        let output = std::process::Output {
+            status,
+            stdout: stdout.join().unwrap_or_default(),
+            stderr: stderr.join().unwrap_or_default(),
+        };
+        output::capture_all(Stream::Stdout, &output.stdout);
+        output::capture_all(Stream::Stderr, &output.stderr);
+        Ok(output)
+    }
+
+    /// ### Runs the command until it succeeds, following the retry policy.
+    /// Its stderr is shown and kept to classify failures, see [`retry::retry`], so it runs untranslated.
+    pub fn status_with_retry(&self) -> std::io::Result<retry::Output> {
+        if !runner::perform(self.operation()) {
+            return Ok(runner::success().into());
+        }
+        retry::retry(&self.program, || {
+            let mut command = self.command();
+            command
+                .env("LC_ALL", "C")
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped());
+            let mut child = self.spawn(&mut command)?;
+            let stdout = tee_stdout(&mut child);
+            let stderr = tee_stderr(&mut child, |line| eprintln!("{}", line));
+            let status = wait(&mut child, self.timeout)?;
+            join(stdout);
+            let stderr = stderr.and_then(|t| t.join().ok()).unwrap_or_default();
+            Ok(retry::Output { status, stderr })
+        })
+    }
+}
+
+/// Waits for `child`, killing it once `timeout` is over.
+fn wait(child: &mut Child, timeout: Option<Duration>) -> std::io::Result<ExitStatus> {
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return child.wait(),
+    };
+    let start = Instant::now();
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+        if start.elapsed() >= timeout {
+            child.kill()?;
+            child.wait()?;
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                format!("timed out after {}s", timeout.as_secs()),
+            ));
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// ### Forwards the stdout of `child` line by line from a thread, capturing it in the install log.
+/// It goes to stderr instead when stdout is reserved for the events, see [`events::reserve_stdout`].
+pub fn tee_stdout(child: &mut Child) -> Option<JoinHandle<()>> {
+    let stdout = child.stdout.take()?;
+    Some(std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+            output::capture(Stream::Stdout, &line);
+            if events::stdout_reserved() {
+                eprintln!("{}", line);
+            } else {
+                println!("{}", line);
+            }
+        }
+    }))
+}
+
+/// ### Forwards the stderr of `child` line by line with `print`, and keeps it.
+/// The returned thread yields everything that was printed once the child closes its stderr.
+/// Every line is also captured in the install log, see [`output::capture`].
+pub fn tee_stderr<P>(child: &mut Child, print: P) -> Option<JoinHandle<String>>
+where
+    P: Fn(&str) + Send + 'static,
+{
+    let stderr = child.stderr.take()?;
+    Some(std::thread::spawn(move || {
+        let mut captured = String::new();
+        for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+            output::capture(Stream::Stderr, &line);
+            print(&line);
+            captured.push_str(&line);
+            captured.push('\n');
+        }
+        captured
+    }))
+}
+
+/// Waits for a forwarding thread, once the command is done.
+fn join<T>(thread: Option<JoinHandle<T>>) {
+    if let Some(thread) = thread {
+        let _ = thread.join();
+    }
+}
+
+/// Reads a pipe to the end from a thread, so that a full pipe never blocks the command.
+fn read_all<R: Read + Send + 'static>(pipe: Option<R>) -> std::thread::JoinHandle<Vec<u8>> {
+    std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_end(&mut buf);
+        }
+        buf
+    })
+}
+
+/// Executes a command directly on the host system.
+///
+/// This is a thin wrapper around [`Cmd::status`] that runs
+/// the given command with the provided arguments.
+pub fn exec(command: &str, args: Vec<String>) -> Result<std::process::ExitStatus, std::io::Error> {
+    Cmd::new(command).args(args).status()
+}
+
+/// Executes a command inside the target system using the configured [`ChrootRunner`].
+///
+/// The arguments are passed to the runner as they are, no shell is involved.
+///
+/// ### Notes
+/// - Assumes `/mnt` is already prepared as a valid chroot environment.
+/// - Relies on `arch-chroot` (or the selected runner) being available in `$PATH`.
+/// - Transient failures are retried following the retry policy, see [`retry::retry`].
+/// - The stderr of the last attempt is kept, so that errors can report it.
+pub fn exec_chroot(command: &str, args: Vec<String>) -> Result<retry::Output, std::io::Error> {
+    Cmd::chroot(command).args(args).status_with_retry()
+}
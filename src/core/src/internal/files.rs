@@ -0,0 +1,103 @@
+use crate::internal::runner::{self, Operation};
+use crate::internal::*;
+use std::fs::{self, File, OpenOptions};
+use std::io::prelude::*;
+use std::path::PathBuf;
+
+pub fn create_file(path: &str) -> Result<()> {
+    let returncode = match runner::perform(Operation::CreateFile(PathBuf::from(path))) {
+        true => File::create(path).map(|_| ()),
+        false => Ok(()),
+    };
+    match returncode {
+        Ok(_) => {
+            log::info!("Create {}", path);
+            Ok(())
+        }
+        Err(e) => Err(InstallError::io(format!("Create {}", path), e)),
+    }
+}
+
+pub fn copy_file(path: &str, destpath: &str) -> Result<()> {
+    let return_code = match runner::perform(Operation::CopyFile {
+        from: PathBuf::from(path),
+        to: PathBuf::from(destpath),
+    }) {
+        true => std::fs::copy(path, destpath).map(|_| ()),
+        false => Ok(()),
+    };
+    match return_code {
+        Ok(_) => {
+            log::info!("Copy {} to {}", path, destpath);
+            Ok(())
+        }
+        Err(e) => Err(InstallError::io(format!("Copy {} to {}", path, destpath), e)),
+    }
+}
+
+pub fn append_file(path: &str, content: &str) -> std::io::Result<()> {
+    log::info!("Append '{}' to file {}", content.trim_end(), path);
+    if !runner::perform(Operation::AppendFile {
+        path: PathBuf::from(path),
+        contents: content.to_string(),
+    }) {
+        return Ok(());
+    }
+    let mut file = OpenOptions::new().append(true).open(path)?;
+    file.write_all(format!("\n{content}\n").as_bytes())?;
+    Ok(())
+}
+
+pub fn sed_file(path: &str, find: &str, replace: &str) -> std::io::Result<()> {
+    log::info!("Sed '{}' to '{}' in file {}", find, replace, path);
+    if !runner::perform(Operation::ReplaceInFile {
+        path: PathBuf::from(path),
+        find: find.to_string(),
+        replace: replace.to_string(),
+    }) {
+        return Ok(());
+    }
+    let contents = fs::read_to_string(path)?;
+    let new_contents = contents.replace(find, replace);
+    let mut file = OpenOptions::new().write(true).truncate(true).open(path)?;
+    file.write_all(new_contents.as_bytes())?;
+    Ok(())
+}
+
+pub fn create_directory(path: &str) -> std::io::Result<()> {
+    if runner::perform(Operation::CreateDir(PathBuf::from(path))) {
+        std::fs::create_dir_all(path)?;
+    }
+    log::info!("Create directory {}", path);
+    Ok(())
+}
+
+pub fn write_file(path: &str, content: &str) -> std::io::Result<()> {
+    log::info!("Write file {}", path);
+    if !runner::perform(Operation::WriteFile {
+        path: PathBuf::from(path),
+        contents: content.to_string(),
+    }) {
+        return Ok(());
+    }
+
+    if let Some(parent) = std::path::Path::new(path).parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(path)?;
+    file.write_all(content.as_bytes())?;
+    Ok(())
+}
+
+pub fn remove_file(path: &str) -> std::io::Result<()> {
+    if runner::perform(Operation::RemoveFile(PathBuf::from(path))) {
+        std::fs::remove_file(path)?;
+    }
+    log::info!("Remove {}", path);
+    Ok(())
+}
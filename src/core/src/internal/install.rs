@@ -0,0 +1,106 @@
+// use crate::functions::partition::umount;
+use crate::internal::*;
+use lazy_static::lazy_static;
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+
+
+lazy_static! {
+    /// Packages already installed during this run, so that steps can ask for
+    /// packages that were installed upfront without running pacstrap again.
+    static ref INSTALLED: Mutex<HashSet<String>> = Mutex::new(HashSet::new());
+}
+
+/// Installs a list of packages into the `/mnt` directory using `pacstrap`,
+/// or libalpm when built with the `alpm` feature.
+///
+/// - Progress is shown as it goes, see [`progress::pacstrap`].
+/// - Packages already installed by an earlier call are skipped, and nothing runs if none are left.
+/// - If `attempt_retries` is `true`, transient failures are retried following the
+///   retry policy, see [`retry::retry`].
+/// - If retries are disabled, the command is executed once in "soft" mode:
+///   errors are logged, but are not returned.
+///
+/// The result is passed to [`exec_eval`] (an error on failure) or
+/// [`soft_exec_eval`] (non-fatal on failure), depending on the retry mode.
+pub fn install(pkgs: Vec<String>, attempt_retries: bool) -> Result<()> {
+    let pkgs: Vec<String> = {
+        let installed = INSTALLED.lock().unwrap();
+        pkgs.into_iter().filter(|p| !installed.contains(p)).collect()
+    };
+    if pkgs.is_empty() {
+        log::debug!("All requested packages are already installed");
+        return Ok(());
+    }
+    if attempt_retries {
+        let result = retry::retry("Install packages", || backend(&pkgs));
+        exec_eval(result, &format!("Install packages {}", pkgs.join(", ")))?;
+    } else {
+        let result = backend(&pkgs).map(|output| output.status);
+        let success = matches!(&result, Ok(status) if status.success());
+        soft_exec_eval(result, format!("Install packages {}", pkgs.join(", ")).as_str());
+        if !success {
+            return Ok(());
+        }
+    }
+    INSTALLED.lock().unwrap().extend(pkgs);
+    // umount("/mnt/dev");
+    Ok(())
+}
+
+#[cfg(not(feature = "alpm"))]
+fn backend(pkgs: &[String]) -> std::io::Result<retry::Output> {
+    let mut args: Vec<String> = Vec::new();
+    let cache_dir = cache::cache_dir();
+    if cache_dir.is_some() {
+        // Don't use the target's cache, the pacman option below picks the shared one
+        args.push(String::from("-c"));
+    }
+    if let Some(conf) = offline::pacman_conf() {
+        args.push(String::from("-C"));
+        args.push(String::from(conf));
+    }
+    args.push(String::from("/mnt"));
+    args.extend(pkgs.iter().cloned());
+    if let Some(dir) = cache_dir {
+        args.push(String::from("--cachedir"));
+        args.push(dir.to_string_lossy().to_string());
+    }
+    progress::pacstrap(&args)
+}
+
+#[cfg(feature = "alpm")]
+fn backend(pkgs: &[String]) -> std::io::Result<retry::Output> {
+    libalpm::install(pkgs)
+}
+
+/// Records packages that got into the target without [`install`], so that they are not installed again.
+pub fn mark_installed(pkgs: Vec<String>) {
+    INSTALLED.lock().unwrap().extend(pkgs);
+}
+
+/// ### Installs a package set from the manifests and enables its services.
+/// Every set named `name` whose conditions hold on this machine is composed, see [`manifest::select`].
+pub fn install_set(name: &str, attempt_retries: bool) -> Result<()> {
+    install_selection(manifest::select(name), attempt_retries)
+}
+
+/// Installs the packages of an already composed selection and enables its services.
+pub fn install_selection(selection: manifest::Selection, attempt_retries: bool) -> Result<()> {
+    if !selection.packages.is_empty() {
+        install(selection.packages, attempt_retries)?;
+    }
+    enable_services(selection.services)
+}
+
+/// Enables systemd services in the target system.
+pub fn enable_services(services: Vec<String>) -> Result<()> {
+    for service in services {
+        exec_eval(
+            exec::exec_chroot("systemctl", vec![String::from("enable"), service.clone()]),
+            format!("Enable {}", service).as_str(),
+        )?;
+    }
+    Ok(())
+}
@@ -0,0 +1,635 @@
+use crate::events::{self, Event as ProgressEvent, PackagePhase};
+use crate::internal::exec::*;
+use crate::internal::*;
+use alpm::{
+    Alpm, AnyDownloadEvent, AnyEvent, AnyQuestion, CommitData, Db, DownloadEvent, Event, LogLevel,
+    Package, PrepareData, Progress, Question, SigLevel, TransFlag,
+};
+use indicatif::{MultiProgress, ProgressBar};
+use std::io::IsTerminal;
+use std::os::unix::process::ExitStatusExt;
+use crate::internal::retry::Output;
+use std::process::ExitStatus;
+
+const ROOT: &str = "/mnt";
+const DB_PATH: &str = "/mnt/var/lib/pacman/";
+const CACHE_DIR: &str = "/mnt/var/cache/pacman/pkg/";
+const HOOK_DIR: &str = "/mnt/etc/pacman.d/hooks/";
+/// The live system's keyring is used to check signatures, like pacstrap does without `-K`.
+const GPG_DIR: &str = "/etc/pacman.d/gnupg/";
+const PACMAN_CONF: &str = "/etc/pacman.conf";
+const MIRRORLIST: &str = "/etc/pacman.d/mirrorlist";
+/// pacman's default, `Required DatabaseOptional`
+const DEFAULT_SIG_LEVEL: SigLevel = SigLevel::PACKAGE
+    .union(SigLevel::DATABASE)
+    .union(SigLevel::DATABASE_OPTIONAL);
+
+/// API filesystems mounted in the target while scriptlets and hooks run, like `pacstrap` does.
+const API_FILESYSTEMS: &[(&str, &str, &str, &str)] = &[
+    ("proc", "proc", "/mnt/proc", "nosuid,noexec,nodev"),
+    ("sysfs", "sys", "/mnt/sys", "nosuid,noexec,nodev,ro"),
+    ("devtmpfs", "udev", "/mnt/dev", "mode=0755,nosuid"),
+    (
+        "devpts",
+        "devpts",
+        "/mnt/dev/pts",
+        "mode=0620,gid=5,nosuid,noexec",
+    ),
+    ("tmpfs", "shm", "/mnt/dev/shm", "mode=1777,nosuid,nodev"),
+    ("tmpfs", "run", "/mnt/run", "nosuid,nodev,mode=0755"),
+];
+
+/// A sync repository read from `pacman.conf`.
+struct Repo {
+    name: String,
+    servers: Vec<String>,
+    sig_level: SigLevel,
+}
+
+/// ### Installs packages into `/mnt` with libalpm instead of `pacstrap`.
+/// - Sync databases are registered from the live system's `pacman.conf`, or the offline one, and refreshed.
+/// - Every target is resolved before anything is written, missing packages, unsatisfied
+///   dependencies and conflicts are logged one by one and reported as a failed exit status.
+/// - Download and database errors are returned as [`std::io::Error`], so they can be retried.
+///
+/// The exit status and errors mimic pacstrap's, so [`install`](super::install::install) handles both backends the same way.
+pub fn install(pkgs: &[String]) -> std::io::Result<Output> {
+    if !runner::perform(runner::Operation::Install(pkgs.to_vec())) {
+        return Ok(Output {
+            status: runner::success(),
+            stderr: String::new(),
+        });
+    }
+    std::fs::create_dir_all(DB_PATH)?;
+    std::fs::create_dir_all(CACHE_DIR)?;
+
+    let mut handle = Alpm::new(ROOT, DB_PATH).map_err(|e| alpm_error("Initialize libalpm", e))?;
+    // The first cache directory is the one packages are downloaded to
+    if let Some(dir) = cache::cache_dir() {
+        handle
+            .add_cachedir(dir.to_string_lossy().as_ref())
+            .map_err(|e| alpm_error("Set shared cache directory", e))?;
+    }
+    handle
+        .add_cachedir(CACHE_DIR)
+        .map_err(|e| alpm_error("Set cache directory", e))?;
+    handle
+        .set_gpgdir(GPG_DIR)
+        .map_err(|e| alpm_error("Set GnuPG directory", e))?;
+    handle
+        .add_hookdir(HOOK_DIR)
+        .map_err(|e| alpm_error("Set hook directory", e))?;
+    handle
+        .add_architecture(std::env::consts::ARCH)
+        .map_err(|e| alpm_error("Set architecture", e))?;
+    handle
+        .set_default_siglevel(DEFAULT_SIG_LEVEL)
+        .map_err(|e| alpm_error("Set signature level", e))?;
+    handle.set_parallel_downloads(5);
+    handle.set_check_space(true);
+
+    for repo in read_repos(offline::pacman_conf().unwrap_or(PACMAN_CONF))? {
+        let db = handle
+            .register_syncdb_mut(repo.name.as_str(), repo.sig_level)
+            .map_err(|e| alpm_error(&format!("Register repository {}", repo.name), e))?;
+        for server in &repo.servers {
+            db.add_server(server.as_str())
+                .map_err(|e| alpm_error(&format!("Add server {}", server), e))?;
+        }
+    }
+    set_callbacks(&handle);
+
+    log::info!("Synchronizing package databases");
+    handle
+        .syncdbs_mut()
+        .update(false)
+        .map_err(|e| alpm_error("Synchronize package databases", e))?;
+
+    let (targets, problems) = resolve(&handle, pkgs);
+    if !problems.is_empty() {
+        return Ok(failure(problems));
+    }
+
+    handle
+        .trans_init(TransFlag::NONE)
+        .map_err(|e| alpm_error("Initialize transaction", e))?;
+    for pkg in targets {
+        if let Err(e) = handle.trans_add_pkg(pkg) {
+            log::warn!("Skipping {}: {}", pkg.name(), e);
+        }
+    }
+
+    let problems = match handle.trans_prepare() {
+        Ok(()) => Vec::new(),
+        Err(e) => describe_prepare_error(e.to_string(), e.try_data()),
+    };
+    if !problems.is_empty() {
+        let _ = handle.trans_release();
+        return Ok(failure(problems));
+    }
+
+    let download_size: i64 = handle.trans_add().iter().map(|p| p.download_size()).sum();
+    log::info!(
+        "{} packages to install, {:.1} MiB to download",
+        handle.trans_add().len(),
+        download_size as f64 / 1024.0 / 1024.0
+    );
+
+    if let Err(e) = mount_api_filesystems() {
+        umount_api_filesystems();
+        let _ = handle.trans_release();
+        return Err(std::io::Error::other(e.to_string()));
+    }
+    let committed = match handle.trans_commit() {
+        Ok(()) => Ok(Vec::new()),
+        Err(e)
+            if matches!(
+                e.error(),
+                alpm::Error::Retrieve | alpm::Error::ExternalDownload
+            ) =>
+        {
+            Err(alpm_error("Download packages", e))
+        }
+        Err(e) => Ok(describe_commit_error(e.to_string(), e.try_data())),
+    };
+    umount_api_filesystems();
+    let _ = handle.trans_release();
+
+    let problems = committed?;
+    if !problems.is_empty() {
+        return Ok(failure(problems));
+    }
+    copy_mirrorlist()?;
+    Ok(Output {
+        status: ExitStatus::from_raw(0),
+        stderr: String::new(),
+    })
+}
+
+/// ### Finds a package or a group for every target, returns the packages and what went wrong.
+/// A target may name its repository like pacman allows, e.g. `axos/grub`, it is then only
+/// looked up in that repository.
+fn resolve<'a>(handle: &'a Alpm, pkgs: &[String]) -> (Vec<&'a Package>, Vec<String>) {
+    let mut targets: Vec<&Package> = Vec::new();
+    let mut problems = Vec::new();
+    for pkg in pkgs {
+        let (found, dbs, name): (_, Vec<&Db>, &str) = match pkg.split_once('/') {
+            Some((repo, name)) => match handle.syncdbs().iter().find(|db| db.name() == repo) {
+                Some(db) => (db.pkgs().find_satisfier(name), vec![db], name),
+                None => {
+                    problems.push(format!(
+                        "Repository {} of {} is not configured in pacman.conf",
+                        repo, pkg
+                    ));
+                    continue;
+                }
+            },
+            None => (
+                handle.syncdbs().find_satisfier(pkg.as_str()),
+                handle.syncdbs().iter().collect(),
+                pkg.as_str(),
+            ),
+        };
+        if let Some(found) = found {
+            targets.push(found);
+            continue;
+        }
+        let group: Vec<&Package> = dbs
+            .iter()
+            .filter_map(|db| db.group(name).ok())
+            .flat_map(|group| group.packages().iter())
+            .collect();
+        if group.is_empty() {
+            problems.push(format!("Target not found: {}", pkg));
+        }
+        for member in group {
+            if !targets.iter().any(|t| t.name() == member.name()) {
+                targets.push(member);
+            }
+        }
+    }
+    (targets, problems)
+}
+
+/// Copies the mirrorlist of the live system into the target, like pacstrap does without `-M`.
+fn copy_mirrorlist() -> std::io::Result<()> {
+    if !std::path::Path::new(MIRRORLIST).exists() {
+        return Ok(());
+    }
+    let target = format!("{ROOT}{MIRRORLIST}");
+    std::fs::create_dir_all(std::path::Path::new(&target).parent().unwrap())?;
+    files::copy_file(MIRRORLIST, &target).map_err(|e| std::io::Error::other(e.to_string()))
+}
+
+fn describe_prepare_error(error: String, data: Option<PrepareData>) -> Vec<String> {
+    match data {
+        Some(PrepareData::UnsatisfiedDeps(deps)) => deps
+            .iter()
+            .map(|missing| {
+                format!(
+                    "Unable to satisfy dependency {} required by {}",
+                    missing.depend(),
+                    missing.target()
+                )
+            })
+            .collect(),
+        Some(PrepareData::ConflictingDeps(conflicts)) => conflicts
+            .iter()
+            .map(|conflict| {
+                format!(
+                    "{} and {} are in conflict ({})",
+                    conflict.package1().name(),
+                    conflict.package2().name(),
+                    conflict.reason()
+                )
+            })
+            .collect(),
+        Some(PrepareData::PkgInvalidArch(pkgs)) => pkgs
+            .iter()
+            .map(|pkg| format!("{} is not available for this architecture", pkg.name()))
+            .collect(),
+        None => vec![format!("Failed to prepare transaction: {}", error)],
+    }
+}
+
+fn describe_commit_error(error: String, data: Option<CommitData>) -> Vec<String> {
+    match data {
+        Some(CommitData::FileConflict(conflicts)) => conflicts
+            .iter()
+            .map(|conflict| {
+                format!(
+                    "{} from {} conflicts with another package or an existing file",
+                    conflict.file(),
+                    conflict.target()
+                )
+            })
+            .collect(),
+        Some(CommitData::PkgInvalid(files)) => files
+            .iter()
+            .map(|file| format!("{} is invalid or corrupted", file))
+            .collect(),
+        None => vec![format!("Failed to commit transaction: {}", error)],
+    }
+}
+
+/// Drives the same progress bars as [`progress::pacstrap`] from libalpm's callbacks,
+/// and answers its questions the way `pacman --noconfirm` would.
+fn set_callbacks(handle: &Alpm) {
+    let interactive = std::io::stderr().is_terminal();
+    let multi = MultiProgress::new();
+    let downloads = progress::bar(&multi, "Downloading");
+    let installs = progress::bar(&multi, "Installing");
+    let hooks = progress::bar(&multi, "Hooks");
+    if !interactive {
+        multi.set_draw_target(indicatif::ProgressDrawTarget::hidden());
+    }
+
+    handle.set_dl_cb(
+        downloads,
+        move |file: &str, event: AnyDownloadEvent, downloads: &mut ProgressBar| {
+            // Database syncs are not part of the transaction
+            if file.ends_with(".db") || file.ends_with(".sig") {
+                return;
+            }
+            match event.event() {
+                DownloadEvent::Init(_) => {
+                    downloads.inc_length(1);
+                    if !interactive {
+                        log::info!("Downloading {}", file);
+                    }
+                }
+                DownloadEvent::Completed(_) => {
+                    downloads.inc(1);
+                    downloads.set_message(file.to_string());
+                    progress::package_event(PackagePhase::Download, downloads, file);
+                }
+                DownloadEvent::Retry(_) => log::warn!("Retrying download of {}", file),
+                DownloadEvent::Progress(_) => {}
+            }
+        },
+    );
+
+    handle.set_progress_cb(
+        installs,
+        move |progress: Progress,
+              pkg: &str,
+              percent: i32,
+              howmany: usize,
+              current: usize,
+              installs: &mut ProgressBar| {
+            let adding = matches!(
+                progress,
+                Progress::AddStart
+                    | Progress::UpgradeStart
+                    | Progress::DowngradeStart
+                    | Progress::ReinstallStart
+            );
+            if !adding || percent != 0 {
+                return;
+            }
+            installs.set_length(howmany as u64);
+            installs.set_position(current as u64);
+            installs.set_message(pkg.to_string());
+            progress::package_event(PackagePhase::Install, installs, pkg);
+            if current == howmany {
+                installs.finish_with_message("done");
+            }
+            if !interactive {
+                log::info!("Installing ({}/{}) {}", current, howmany, pkg);
+            }
+        },
+    );
+
+    handle.set_event_cb(
+        hooks,
+        move |event: AnyEvent, hooks: &mut ProgressBar| match event.event() {
+            Event::HookRunStart(hook) => {
+                hooks.set_length(hook.total() as u64);
+                hooks.set_position(hook.position() as u64);
+                hooks.set_message(hook.name().to_string());
+                progress::package_event(PackagePhase::Hook, hooks, hook.name());
+                if !interactive {
+                    log::info!(
+                        "Running hook ({}/{}) {}",
+                        hook.position(),
+                        hook.total(),
+                        hook.name()
+                    );
+                }
+            }
+            Event::TransactionDone => {
+                hooks.finish_with_message("done");
+                let _ = multi.clear();
+            }
+            Event::ScriptletInfo(info) => log::info!("{}", info.line().trim_end()),
+            _ => {}
+        },
+    );
+
+    handle.set_question_cb((), |question: AnyQuestion, _: &mut ()| {
+        let prompt = |question: String, answer: &str| {
+            events::emit(ProgressEvent::Prompt {
+                question,
+                answer: answer.to_string(),
+            })
+        };
+        match question.question() {
+            Question::Replace(replace) => {
+                prompt(
+                    format!(
+                        "Replace {} with {}?",
+                        replace.oldpkg().name(),
+                        replace.newpkg().name()
+                    ),
+                    "yes",
+                );
+                replace.set_replace(true)
+            }
+            Question::ImportKey(mut key) => {
+                prompt(
+                    format!("Import PGP key {}, \"{}\"?", key.fingerprint(), key.uid()),
+                    "yes",
+                );
+                key.set_import(true)
+            }
+            Question::Corrupted(mut corrupted) => {
+                prompt(
+                    format!("Remove corrupted package {}?", corrupted.filepath()),
+                    "yes",
+                );
+                log::warn!("Removing corrupted package {}", corrupted.filepath());
+                corrupted.set_remove(true);
+            }
+            Question::Conflict(conflict) => {
+                prompt(
+                    format!(
+                        "{} and {} are in conflict. Remove {}?",
+                        conflict.conflict().package1().name(),
+                        conflict.conflict().package2().name(),
+                        conflict.conflict().package2().name()
+                    ),
+                    "no",
+                );
+                log::warn!(
+                    "{} conflicts with {}, keeping {}",
+                    conflict.conflict().package1().name(),
+                    conflict.conflict().package2().name(),
+                    conflict.conflict().package2().name()
+                )
+            }
+            _ => {}
+        }
+    });
+
+    handle.set_log_cb((), |level: LogLevel, message: &str, _: &mut ()| {
+        let message = message.trim_end();
+        if level.contains(LogLevel::ERROR) {
+            log::error!("libalpm: {}", message);
+        } else if level.contains(LogLevel::WARNING) {
+            log::warn!("libalpm: {}", message);
+        } else {
+            log::trace!("libalpm: {}", message);
+        }
+    });
+}
+
+/// ### Reads the sync repositories from a `pacman.conf`.
+/// `Server` lines are taken as is, `Include` files are read for their `Server` lines,
+/// and `$repo`/`$arch` are expanded. The `SigLevel` of a repository applies over the one
+/// of `[options]`, see [`parse_sig_level`].
+fn read_repos(path: &str) -> std::io::Result<Vec<Repo>> {
+    let content = std::fs::read_to_string(path)?;
+    let mut repos: Vec<Repo> = Vec::new();
+    let mut default = DEFAULT_SIG_LEVEL;
+    for line in content.lines() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            if section != "options" {
+                repos.push(Repo {
+                    name: section.to_string(),
+                    servers: Vec::new(),
+                    sig_level: default,
+                });
+            }
+            continue;
+        }
+        let (key, value) = match line.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim()),
+            None => continue,
+        };
+        let repo = match repos.last_mut() {
+            Some(repo) => repo,
+            None => {
+                if key == "SigLevel" {
+                    default = parse_sig_level(value, default);
+                }
+                continue;
+            }
+        };
+        match key {
+            "Server" => repo.servers.push(expand_server(value, &repo.name)),
+            "Include" => {
+                let mirrorlist = std::fs::read_to_string(value).unwrap_or_default();
+                for mirror in mirrorlist.lines() {
+                    let mirror = mirror.split('#').next().unwrap_or_default().trim();
+                    if let Some((key, server)) = mirror.split_once('=') {
+                        if key.trim() == "Server" {
+                            repo.servers.push(expand_server(server.trim(), &repo.name));
+                        }
+                    }
+                }
+            }
+            "SigLevel" => repo.sig_level = parse_sig_level(value, repo.sig_level),
+            _ => {}
+        }
+    }
+    Ok(repos)
+}
+
+/// ### Applies a `SigLevel` value of `pacman.conf` over `level`, the way pacman does.
+/// `Never`, `Optional` and `Required` set whether signatures are checked, `TrustedOnly` and `TrustAll`
+/// which keys are trusted. A `Package` or `Database` prefix limits an option to packages or databases.
+fn parse_sig_level(value: &str, mut level: SigLevel) -> SigLevel {
+    for option in value.split_whitespace() {
+        let (packages, databases, name) = if let Some(name) = option.strip_prefix("Package") {
+            (true, false, name)
+        } else if let Some(name) = option.strip_prefix("Database") {
+            (false, true, name)
+        } else {
+            (true, true, option)
+        };
+        if !["Never", "Optional", "Required", "TrustedOnly", "TrustAll"].contains(&name) {
+            log::warn!("Ignoring unknown SigLevel option {}", option);
+            continue;
+        }
+        let flags = [
+            (
+                packages,
+                SigLevel::PACKAGE,
+                SigLevel::PACKAGE_OPTIONAL,
+                SigLevel::PACKAGE_MARGINAL_OK | SigLevel::PACKAGE_UNKNOWN_OK,
+            ),
+            (
+                databases,
+                SigLevel::DATABASE,
+                SigLevel::DATABASE_OPTIONAL,
+                SigLevel::DATABASE_MARGINAL_OK | SigLevel::DATABASE_UNKNOWN_OK,
+            ),
+        ];
+        for (applies, check, optional, trust_all) in flags {
+            if !applies {
+                continue;
+            }
+            match name {
+                "Never" => level.remove(check),
+                "Optional" => level.insert(check | optional),
+                "Required" => {
+                    level.insert(check);
+                    level.remove(optional);
+                }
+                "TrustedOnly" => level.remove(trust_all),
+                _ => level.insert(trust_all),
+            }
+        }
+    }
+    level
+}
+
+fn expand_server(server: &str, repo: &str) -> String {
+    server
+        .replace("$repo", repo)
+        .replace("$arch", std::env::consts::ARCH)
+}
+
+fn mount_api_filesystems() -> Result<()> {
+    for (fstype, source, target, options) in API_FILESYSTEMS {
+        let _ = std::fs::create_dir_all(target);
+        exec_eval(
+            exec(
+                "mount",
+                vec![
+                    String::from("-t"),
+                    String::from(*fstype),
+                    String::from(*source),
+                    String::from(*target),
+                    String::from("-o"),
+                    String::from(*options),
+                ],
+            ),
+            format!("Mount {} at {}", fstype, target).as_str(),
+        )?;
+    }
+    Ok(())
+}
+
+fn umount_api_filesystems() {
+    for (_, _, target, _) in API_FILESYSTEMS.iter().rev() {
+        soft_exec_eval(
+            exec("umount", vec![String::from(*target)]),
+            format!("Unmount {}", target).as_str(),
+        );
+    }
+}
+
+fn alpm_error(context: &str, error: impl std::fmt::Display) -> std::io::Error {
+    std::io::Error::other(format!("{}: {}", context, error))
+}
+
+/// Logs every problem and returns them with a failed exit status, so that they can be classified.
+fn failure(problems: Vec<String>) -> Output {
+    for problem in &problems {
+        log::error!("{}", problem);
+    }
+    Output {
+        status: ExitStatus::from_raw(1 << 8),
+        stderr: problems.join("\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sig_levels_apply_over_the_default() {
+        assert_eq!(
+            parse_sig_level("Optional TrustAll", DEFAULT_SIG_LEVEL),
+            SigLevel::PACKAGE
+                | SigLevel::PACKAGE_OPTIONAL
+                | SigLevel::PACKAGE_MARGINAL_OK
+                | SigLevel::PACKAGE_UNKNOWN_OK
+                | SigLevel::DATABASE
+                | SigLevel::DATABASE_OPTIONAL
+                | SigLevel::DATABASE_MARGINAL_OK
+                | SigLevel::DATABASE_UNKNOWN_OK
+        );
+        assert_eq!(
+            parse_sig_level("Never", DEFAULT_SIG_LEVEL),
+            SigLevel::DATABASE_OPTIONAL
+        );
+        assert_eq!(
+            parse_sig_level("Required DatabaseOptional TrustedOnly", SigLevel::NONE),
+            DEFAULT_SIG_LEVEL
+        );
+        assert_eq!(
+            parse_sig_level("PackageRequired DatabaseNever", DEFAULT_SIG_LEVEL),
+            SigLevel::PACKAGE | SigLevel::DATABASE_OPTIONAL
+        );
+    }
+
+    #[test]
+    fn repositories_inherit_the_options_sig_level() {
+        let path = std::env::temp_dir().join(format!("axinstall-sig-{}.conf", std::process::id()));
+        std::fs::write(
+            &path,
+            "[options]\nSigLevel = Never\n\n[core]\nServer = https://example.org\n\n\
+             [offline]\nSigLevel = Optional TrustAll\nServer = file:///repo\n",
+        )
+        .unwrap();
+        let repos = read_repos(&path.to_string_lossy()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(repos[0].sig_level, SigLevel::DATABASE_OPTIONAL);
+        assert!(repos[1].sig_level.contains(SigLevel::PACKAGE_OPTIONAL));
+        assert!(repos[1].sig_level.contains(SigLevel::PACKAGE_UNKNOWN_OK));
+    }
+}
@@ -0,0 +1,194 @@
+use crate::functions::hardware::{self, Chassis, Firmware, GpuDriver};
+use crate::internal::*;
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// The newest manifest format this binary understands.
+const MANIFEST_VERSION: u32 = 1;
+/// Manifests shipped with the package.
+const SYSTEM_MANIFESTS: &str = "/usr/share/axinstall/manifests";
+/// Local overrides, a file here replaces the shipped file with the same name.
+const LOCAL_MANIFESTS: &str = "/etc/axinstall/manifests";
+
+/// Built in copies of the shipped manifests, used when they are not installed.
+const BUILTIN_MANIFESTS: &[(&str, &str)] = &[
+    ("base.json", include_str!("../../../manifests/base.json")),
+    ("desktops.json", include_str!("../../../manifests/desktops.json")),
+    ("userkits.json", include_str!("../../../manifests/userkits.json")),
+];
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    sets: Vec<PackageSet>,
+}
+
+/// A named list of packages and services. Several sets can share a name,
+/// they are composed together when their conditions hold.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageSet {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub packages: Vec<String>,
+    #[serde(default)]
+    pub services: Vec<String>,
+    #[serde(default)]
+    pub conditions: Conditions,
+}
+
+/// Restricts a set to some machines. Unset fields always match.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Conditions {
+    pub firmware: Option<Firmware>,
+    /// Matches if any GPU uses this driver stack
+    pub gpu: Option<GpuDriver>,
+    pub chassis: Option<Chassis>,
+    /// Whether the chassis is a laptop, `false` matches every other chassis
+    pub laptop: Option<bool>,
+    #[serde(rename = "virtual")]
+    pub virtual_machine: Option<bool>,
+}
+
+/// What the conditions are evaluated against.
+#[derive(Debug, Clone)]
+pub struct Facts {
+    pub firmware: Firmware,
+    pub gpus: Vec<GpuDriver>,
+    pub chassis: Chassis,
+    pub virtual_machine: bool,
+}
+
+impl Facts {
+    /// Detects the facts of the machine we are running on.
+    pub fn detect() -> Self {
+        Facts {
+            firmware: hardware::detect_firmware(),
+            gpus: hardware::detect_gpus()
+                .into_iter()
+                .map(|gpu| gpu.driver_stack)
+                .collect(),
+            chassis: hardware::detect_chassis(),
+            virtual_machine: hardware::detect_hypervisor().is_virtual(),
+        }
+    }
+}
+
+impl Conditions {
+    fn matches(&self, facts: &Facts) -> bool {
+        self.firmware.is_none_or(|f| f == facts.firmware)
+            && self.gpu.is_none_or(|g| facts.gpus.contains(&g))
+            && self.chassis.is_none_or(|c| c == facts.chassis)
+            && self.laptop.is_none_or(|l| l == (facts.chassis == Chassis::Laptop))
+            && self.virtual_machine.is_none_or(|v| v == facts.virtual_machine)
+    }
+}
+
+/// The packages and services of every matching set, without duplicates.
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    pub packages: Vec<String>,
+    pub services: Vec<String>,
+}
+
+lazy_static! {
+    /// The error is kept as a message, it is reported by [`check`] before any set is used.
+    static ref SETS: std::result::Result<Vec<PackageSet>, String> = load_manifests();
+    static ref FACTS: Facts = Facts::detect();
+}
+
+/// ### Loads every manifest, later directories replacing files of the same name.
+/// Built in manifests come first, then `/usr/share/axinstall/manifests`, then `/etc/axinstall/manifests`.
+fn load_manifests() -> std::result::Result<Vec<PackageSet>, String> {
+    let mut files: BTreeMap<String, (String, String)> = BTreeMap::new();
+    for (name, content) in BUILTIN_MANIFESTS {
+        files.insert(
+            name.to_string(),
+            (format!("builtin {name}"), content.to_string()),
+        );
+    }
+    for dir in [SYSTEM_MANIFESTS, LOCAL_MANIFESTS] {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            match std::fs::read_to_string(&path) {
+                Ok(content) => {
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    files.insert(name, (path.to_string_lossy().to_string(), content));
+                }
+                Err(e) => return Err(format!("Read manifest {path:?}  ERROR: {}", e)),
+            }
+        }
+    }
+
+    let mut sets = Vec::new();
+    for (source, content) in files.into_values() {
+        let manifest: Manifest = match serde_json::from_str(&content) {
+            Ok(manifest) => manifest,
+            Err(e) => return Err(format!("Parse manifest {source}  ERROR: {}", e)),
+        };
+        if manifest.version > MANIFEST_VERSION {
+            return Err(format!(
+                "Manifest {source} has version {}, only up to {MANIFEST_VERSION} is supported",
+                manifest.version
+            ));
+        }
+        log::debug!("Loaded {} package sets from {}", manifest.sets.len(), source);
+        sets.extend(manifest.sets);
+    }
+    Ok(sets)
+}
+
+/// Loads the manifests, returning why they can't be used.
+pub fn check() -> Result<()> {
+    match &*SETS {
+        Ok(_) => Ok(()),
+        Err(e) => Err(InstallError::invalid(e.clone())),
+    }
+}
+
+/// Composes every set named `name` whose conditions hold on this machine.
+pub fn select(name: &str) -> Selection {
+    select_with(name, &FACTS)
+}
+
+/// Composes every set named `name` whose conditions hold for `facts`.
+pub fn select_with(name: &str, facts: &Facts) -> Selection {
+    let mut selection = Selection::default();
+    let mut found = false;
+    let sets = SETS.as_deref().unwrap_or_default();
+    for set in sets.iter().filter(|set| set.name == name) {
+        found = true;
+        if !set.conditions.matches(facts) {
+            log::debug!("Skipping set {} ({}): conditions not met", set.name, set.description);
+            continue;
+        }
+        for package in &set.packages {
+            if !selection.packages.contains(package) {
+                selection.packages.push(package.clone());
+            }
+        }
+        for service in &set.services {
+            if !selection.services.contains(service) {
+                selection.services.push(service.clone());
+            }
+        }
+    }
+    if !found {
+        log::warn!("No package set named {} in the manifests", name);
+    }
+    selection
+}
+
+/// The facts of the running machine, as used by [`select`].
+pub fn facts() -> Facts {
+    FACTS.clone()
+}
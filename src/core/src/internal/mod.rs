@@ -0,0 +1,21 @@
+pub mod cache;
+pub mod config;
+pub mod error;
+pub mod exec;
+pub mod files;
+pub mod install;
+#[cfg(feature = "alpm")]
+pub mod libalpm;
+pub mod manifest;
+pub mod offline;
+pub mod output;
+pub mod packages;
+pub mod preflight;
+pub mod progress;
+pub mod retry;
+pub mod returncode_eval;
+pub mod runner;
+
+pub use install::install;
+pub use returncode_eval::*;
+pub use error::{InstallError, Result};
\ No newline at end of file
@@ -0,0 +1,178 @@
+use crate::functions::pacman;
+use crate::internal::exec::*;
+use crate::internal::*;
+use lazy_static::lazy_static;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const OFFLINE_CONF: &str = "/tmp/axinstall-offline/pacman.conf";
+/// Sync databases of the local repository, kept apart from the live system's
+const OFFLINE_DBPATH: &str = "/tmp/axinstall-offline/db";
+
+/// A local pacman repository, made with `repo-add`, used instead of the mirrors.
+#[derive(Debug, Clone)]
+struct OfflineRepo {
+    name: String,
+    path: PathBuf,
+}
+
+lazy_static! {
+    static ref OFFLINE: Mutex<Option<OfflineRepo>> = Mutex::new(None);
+}
+
+/// ### Installs every package from a local repository instead of the mirrors.
+/// `path` is a directory, or a mounted ISO, holding the `<name>.db` made by `repo-add` and the packages.
+/// A temporary `pacman.conf` with `Server = file://<path>` is generated and used by every install.
+pub fn enable(path: &Path) -> Result<()> {
+    let path = path
+        .canonicalize()
+        .map_err(|e| InstallError::io(format!("Offline repository {path:?}"), e))?;
+    let name = match find_repo_name(&path) {
+        Some(name) => name,
+        None => {
+            return Err(InstallError::invalid(format!(
+                "No pacman repository database (*.db) found in {path:?}"
+            )))
+        }
+    };
+    log::info!("Installing offline from repository {} in {:?}", name, path);
+    files_eval(
+        files::create_directory(OFFLINE_DBPATH),
+        "Create offline package database directory",
+    )?;
+    files_eval(
+        files::write_file(
+            OFFLINE_CONF,
+            &format!(
+                "# Generated by axinstall for an offline installation\n\
+                 [options]\n\
+                 Architecture = auto\n\
+                 SigLevel = Required DatabaseOptional\n\
+                 LocalFileSigLevel = Optional\n\
+                 \n\
+                 [{name}]\n\
+                 SigLevel = Optional TrustAll\n\
+                 Server = file://{}\n",
+                path.display()
+            ),
+        ),
+        "Write offline pacman.conf",
+    )?;
+    exec_eval(
+        exec(
+            "pacman",
+            vec![
+                String::from("--config"),
+                String::from(OFFLINE_CONF),
+                String::from("--dbpath"),
+                String::from(OFFLINE_DBPATH),
+                String::from("-Sy"),
+            ],
+        ),
+        "Synchronize offline repository database",
+    )?;
+    *OFFLINE.lock().unwrap() = Some(OfflineRepo { name, path });
+    Ok(())
+}
+
+/// The repository name is the name of its database, `custom.db` for `repo-add custom.db.tar.gz`.
+fn find_repo_name(path: &Path) -> Option<String> {
+    let mut names: Vec<String> = std::fs::read_dir(path)
+        .ok()?
+        .flatten()
+        .filter_map(|entry| {
+            let file = entry.file_name().to_string_lossy().to_string();
+            file.strip_suffix(".db").map(String::from)
+        })
+        .collect();
+    names.sort();
+    if names.len() > 1 {
+        log::warn!("Several repositories in the offline source, using {}", names[0]);
+    }
+    names.into_iter().next()
+}
+
+pub fn is_enabled() -> bool {
+    OFFLINE.lock().unwrap().is_some()
+}
+
+/// ### The `pacman.conf` packages are checked and installed with, the live one if `None`.
+/// That is the generated offline one when installing offline, or the one generated
+/// from the pacman settings, see [`pacman::install_conf`].
+pub fn pacman_conf() -> Option<&'static str> {
+    if is_enabled() {
+        return Some(OFFLINE_CONF);
+    }
+    pacman::install_conf()
+}
+
+/// ### Writes the `pacman.conf` the target should use during the installation, see [`pacman::generate`].
+/// When offline, that is the generated one and the repository is bind mounted at the same
+/// path in `/mnt`, so that pacman also works from inside the chroot.
+pub fn copy_pacman_conf() -> Result<()> {
+    let repo = OFFLINE.lock().unwrap().clone();
+    match repo {
+        None => pacman::write_pacman_conf(),
+        Some(repo) => {
+            files::copy_file(OFFLINE_CONF, "/mnt/etc/pacman.conf")?;
+            let target = target_path(&repo.path);
+            if is_mounted(&target) {
+                return Ok(());
+            }
+            files_eval(
+                files::create_directory(&target.to_string_lossy()),
+                "Create offline repository mountpoint",
+            )?;
+            exec_eval(
+                exec(
+                    "mount",
+                    vec![
+                        String::from("--bind"),
+                        repo.path.to_string_lossy().to_string(),
+                        target.to_string_lossy().to_string(),
+                    ],
+                ),
+                format!("Bind mount offline repository {} in the target", repo.name).as_str(),
+            )
+        }
+    }
+}
+
+/// ### Ends an offline installation.
+/// The repository is unmounted from the target, and with `restore_online` the online
+/// `pacman.conf` replaces the offline one, otherwise the target keeps using the local repository.
+pub fn finish(restore_online: bool) -> Result<()> {
+    let repo = OFFLINE.lock().unwrap().clone();
+    if let Some(repo) = repo {
+        let target = target_path(&repo.path);
+        if is_mounted(&target) {
+            soft_exec_eval(
+                exec("umount", vec![target.to_string_lossy().to_string()]),
+                "Unmount offline repository from the target",
+            );
+        }
+    }
+    if restore_online {
+        restore_online_conf()?;
+    }
+    Ok(())
+}
+
+/// Puts the online `pacman.conf` back in the target.
+fn restore_online_conf() -> Result<()> {
+    pacman::write_pacman_conf()?;
+    log::info!("Restored the online pacman.conf in the target");
+    Ok(())
+}
+
+fn target_path(path: &Path) -> PathBuf {
+    Path::new("/mnt").join(path.strip_prefix("/").unwrap_or(path))
+}
+
+fn is_mounted(path: &Path) -> bool {
+    let mounts = std::fs::read_to_string("/proc/mounts").unwrap_or_default();
+    let path = path.to_string_lossy();
+    mounts
+        .lines()
+        .any(|line| line.split_whitespace().nth(1) == Some(path.as_ref()))
+}
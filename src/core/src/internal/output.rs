@@ -0,0 +1,91 @@
+//! ### What commands print, kept in the install log.
+//! Every line is logged under [`TARGET`], tagged with the running [`Step`], so that front-ends
+//! can keep these lines out of the terminal, where the commands already print them.
+//! Passwords and hashes never reach the log, see [`redact`].
+
+use crate::installer::Step;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::sync::Mutex;
+
+/// The log target of the captured lines.
+pub const TARGET: &str = "axinstall::output";
+
+const REDACTED: &str = "[redacted]";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+lazy_static! {
+    static ref STEP: Mutex<Option<Step>> = Mutex::new(None);
+    static ref SECRETS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    /// crypt(3) hashes, e.g. `$6$salt$hash` or `$y$j9T$salt$hash`
+    static ref HASH: Regex = Regex::new(r#"\$(1|2[abxy]?|5|6|7|y|gy)\$[^\s:'"]+"#).unwrap();
+}
+
+/// Tags the lines captured from now on with `step`, `None` once the steps are done.
+pub fn set_step(step: Option<Step>) {
+    *STEP.lock().unwrap() = step;
+}
+
+/// Redacts `secret` from the log from now on, e.g. a password given on the command line.
+pub fn add_secret(secret: &str) {
+    let secret = secret.trim();
+    let mut secrets = SECRETS.lock().unwrap();
+    if !secret.is_empty() && !secrets.iter().any(|s| s == secret) {
+        secrets.push(secret.to_string());
+    }
+}
+
+/// ### `line` with the secrets added with [`add_secret`] and every password hash replaced.
+/// Secrets are only replaced as whole words, so that a short password doesn't garble every line.
+pub fn redact(line: &str) -> String {
+    let mut line = line.to_string();
+    for secret in SECRETS.lock().unwrap().iter() {
+        line = replace_word(&line, secret);
+    }
+    HASH.replace_all(&line, REDACTED).to_string()
+}
+
+fn replace_word(line: &str, word: &str) -> String {
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric());
+    let mut redacted = String::new();
+    let mut rest = 0;
+    for (start, _) in line.match_indices(word) {
+        let end = start + word.len();
+        if start < rest
+            || is_word(line[..start].chars().next_back())
+            || is_word(line[end..].chars().next())
+        {
+            continue;
+        }
+        redacted.push_str(&line[rest..start]);
+        redacted.push_str(REDACTED);
+        rest = end;
+    }
+    redacted.push_str(&line[rest..]);
+    redacted
+}
+
+/// Logs a line printed by a command, e.g. `[Base] stderr: warning: ...`.
+pub fn capture(stream: Stream, line: &str) {
+    let step = match *STEP.lock().unwrap() {
+        Some(step) => format!("{:?}", step),
+        None => String::from("-"),
+    };
+    let stream = match stream {
+        Stream::Stdout => "stdout",
+        Stream::Stderr => "stderr",
+    };
+    log::info!(target: TARGET, "[{}] {}: {}", step, stream, redact(line));
+}
+
+/// Logs everything a command printed once it is done, for commands whose output is captured.
+pub fn capture_all(stream: Stream, output: &[u8]) {
+    for line in String::from_utf8_lossy(output).lines() {
+        capture(stream, line);
+    }
+}
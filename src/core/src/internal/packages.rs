@@ -0,0 +1,154 @@
+use crate::internal::*;
+use std::path::PathBuf;
+
+/// ### Gathers the packages of every enabled step before installing anything.
+/// Packages requested by several steps are only kept once, so that the whole
+/// installation needs a single `pacstrap` transaction for the required packages,
+/// and a second, non-fatal one for the optional packages (user kits and extras).
+#[derive(Debug, Default)]
+pub struct PackagePlan {
+    required: Vec<String>,
+    optional: Vec<String>,
+}
+
+impl PackagePlan {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds packages needed by `step`, failing to install them aborts the installation.
+    pub fn require(&mut self, step: &str, packages: Vec<String>) {
+        log::debug!("Packages for {} : {:?}", step, packages);
+        for package in packages {
+            self.optional.retain(|p| p != &package);
+            if !self.required.contains(&package) {
+                self.required.push(package);
+            } else {
+                log::debug!("{} is already planned, skipping duplicate from {}", package, step);
+            }
+        }
+    }
+
+    /// Adds packages wanted by `step`, failing to install them is only logged.
+    pub fn want(&mut self, step: &str, packages: Vec<String>) {
+        log::debug!("Optional packages for {} : {:?}", step, packages);
+        for package in packages {
+            if !self.required.contains(&package) && !self.optional.contains(&package) {
+                self.optional.push(package);
+            } else {
+                log::debug!("{} is already planned, skipping duplicate from {}", package, step);
+            }
+        }
+    }
+
+    pub fn required(&self) -> &[String] {
+        &self.required
+    }
+
+    pub fn optional(&self) -> &[String] {
+        &self.optional
+    }
+
+    /// ### Resolves the whole plan against the sync databases before anything is written to disk.
+    /// - Missing required packages, conflicts and unsatisfiable dependencies are reported as failures.
+    /// - Missing optional packages are only warned about, and dropped from the plan
+    ///   so that they do not make the whole optional transaction fail.
+    pub fn check(&mut self) -> Result<PlanReport> {
+        preflight::ensure_synced()?;
+        let all: Vec<String> = self.required.iter().chain(&self.optional).cloned().collect();
+        let resolution = preflight::resolve(&all)?;
+
+        let mut report = PlanReport {
+            packages: resolution.packages.len(),
+            problems: resolution.problems.clone(),
+            download_size: resolution.download_size(),
+            ..PlanReport::default()
+        };
+        for package in resolution.missing {
+            if self.required.contains(&package) {
+                report.missing_required.push(package);
+            } else {
+                report.missing_optional.push(package);
+            }
+        }
+        self.optional.retain(|p| !report.missing_optional.contains(p));
+        let names: Vec<String> = resolution.packages.into_iter().map(|(name, _)| name).collect();
+        report.install_size = preflight::installed_size(&names);
+        Ok(report)
+    }
+
+    /// ### Downloads every planned package before anything is written to disk.
+    /// The shared package cache is used, the live system's one if none was set,
+    /// so that a network failure can not leave a half installed disk.
+    /// Where they went is recorded for later runs, see [`cache::use_prefetched`].
+    pub fn prefetch(&self) -> Result<()> {
+        if offline::is_enabled() {
+            log::info!("Installing offline, nothing to prefetch");
+            return Ok(());
+        }
+        let cache_dir = match cache::cache_dir() {
+            Some(dir) => dir,
+            None => {
+                cache::set_cache_dir(std::path::Path::new(cache::HOST_CACHE))?;
+                PathBuf::from(cache::HOST_CACHE)
+            }
+        };
+        let all: Vec<String> = self.required.iter().chain(&self.optional).cloned().collect();
+        exec_eval(
+            preflight::download(&all, &cache_dir),
+            format!("Prefetch {} packages into {:?}", all.len(), cache_dir).as_str(),
+        )?;
+        cache::record_prefetch(&cache_dir)
+    }
+
+    /// Installs the required packages, then the optional ones, in as few transactions as possible.
+    pub fn install(self) -> Result<()> {
+        log::info!(
+            "Installing {} required and {} optional packages",
+            self.required.len(),
+            self.optional.len()
+        );
+        if !self.required.is_empty() {
+            install(self.required, true)?;
+        }
+        if !self.optional.is_empty() {
+            install(self.optional, false)?;
+        }
+        Ok(())
+    }
+}
+
+/// What the pre-flight check of a [`PackagePlan`] found.
+#[derive(Debug, Default)]
+pub struct PlanReport {
+    /// Number of packages to install, dependencies included
+    pub packages: usize,
+    pub missing_required: Vec<String>,
+    pub missing_optional: Vec<String>,
+    /// Conflicts and unsatisfiable dependencies
+    pub problems: Vec<String>,
+    pub download_size: u64,
+    pub install_size: u64,
+}
+
+impl PlanReport {
+    /// Whether the installation can go on, missing optional packages are not fatal.
+    pub fn is_ok(&self) -> bool {
+        self.missing_required.is_empty() && self.problems.is_empty()
+    }
+
+    pub fn log(&self) {
+        log::info!("Packages to install : {}", self.packages);
+        log::info!("Download size : {}", preflight::human_size(self.download_size));
+        log::info!("Installed size : {}", preflight::human_size(self.install_size));
+        for package in &self.missing_required {
+            log::error!("Package not found : {}", package);
+        }
+        for package in &self.missing_optional {
+            log::warn!("Optional package not found, skipping : {}", package);
+        }
+        for problem in &self.problems {
+            log::error!("{}", problem);
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use crate::internal::exec::Cmd;
+use crate::internal::*;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// A scratch database directory: fresh sync databases and an empty local database,
+/// so that packages resolve as they would in the empty target, not against the live system.
+const PLAN_DBPATH: &str = "/tmp/axinstall-plan";
+/// An unreachable mirror fails the check instead of hanging it.
+const SYNC_TIMEOUT: Duration = Duration::from_secs(300);
+/// Whether the scratch database was synchronized by this process.
+static SYNCED: AtomicBool = AtomicBool::new(false);
+
+/// What resolving a list of packages against the sync databases found.
+#[derive(Debug, Default)]
+pub struct Resolution {
+    /// Every package of the transaction, dependencies included, with its download size
+    pub packages: Vec<(String, u64)>,
+    /// Requested packages that are neither a package, a provider nor a group
+    pub missing: Vec<String>,
+    /// Conflicts and unsatisfiable dependencies, as reported by pacman
+    pub problems: Vec<String>,
+}
+
+impl Resolution {
+    pub fn download_size(&self) -> u64 {
+        self.packages.iter().map(|(_, size)| size).sum()
+    }
+}
+
+/// ### Creates the scratch database and synchronizes it.
+/// The live system's own databases are left untouched.
+pub fn sync_databases() -> Result<()> {
+    files_eval(
+        files::create_directory(&format!("{PLAN_DBPATH}/local")),
+        "Create pre-flight package database",
+    )?;
+    files_eval(
+        files::write_file(&format!("{PLAN_DBPATH}/local/ALPM_DB_VERSION"), "9\n"),
+        "Initialize pre-flight local database",
+    )?;
+    let output = pacman(&["-Sy"]).timeout(SYNC_TIMEOUT).output();
+    match output {
+        Ok(output) if output.status.success() => {
+            log::info!("Synchronize pre-flight package databases");
+            SYNCED.store(true, Ordering::Relaxed);
+            Ok(())
+        }
+        Ok(output) => Err(InstallError::Command {
+            context: String::from("Synchronize pre-flight package databases"),
+            code: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        }),
+        Err(e) => Err(InstallError::io("Synchronize pre-flight package databases", e)),
+    }
+}
+
+/// Synchronizes the scratch database unless that was already done, see [`sync_databases`].
+pub fn ensure_synced() -> Result<()> {
+    if SYNCED.load(Ordering::Relaxed) {
+        return Ok(());
+    }
+    sync_databases()
+}
+
+/// ### Checks whether a package is in the scratch sync databases, see [`ensure_synced`].
+/// They come from the `pacman.conf` packages are installed with, extra and offline repositories included.
+pub fn in_sync_db(package: &str) -> bool {
+    pacman(&["-Si", package])
+        .output()
+        .map(|output| output.status.success())
+        .unwrap_or(false)
+}
+
+/// ### Resolves packages and their dependencies with `pacman -Sp`, without installing anything.
+/// Missing targets are collected first, then the others are resolved again to find conflicts and sizes.
+pub fn resolve(pkgs: &[String]) -> Result<Resolution> {
+    let mut resolution = resolve_once(pkgs)?;
+    if !resolution.missing.is_empty() {
+        let found: Vec<String> = pkgs
+            .iter()
+            .filter(|p| !resolution.missing.contains(p))
+            .cloned()
+            .collect();
+        let missing = resolution.missing;
+        resolution = resolve_once(&found)?;
+        resolution.missing = missing;
+    }
+    Ok(resolution)
+}
+
+fn resolve_once(pkgs: &[String]) -> Result<Resolution> {
+    let mut resolution = Resolution::default();
+    if pkgs.is_empty() {
+        return Ok(resolution);
+    }
+    let mut args = vec!["-Sp", "--noconfirm", "--print-format", "%n %s"];
+    args.extend(pkgs.iter().map(String::as_str));
+    let output = match pacman(&args).output() {
+        Ok(output) => output,
+        Err(e) => return Err(InstallError::io("Resolve packages", e)),
+    };
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        if let Some((name, size)) = line.split_once(' ') {
+            if let Ok(size) = size.trim().parse() {
+                resolution.packages.push((name.to_string(), size));
+                continue;
+            }
+        }
+        parse_problem(line, &mut resolution);
+    }
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        parse_problem(line, &mut resolution);
+    }
+    if !output.status.success() && resolution.missing.is_empty() && resolution.problems.is_empty() {
+        resolution.problems.push(format!(
+            "pacman exited with code {}",
+            output.status.code().unwrap_or(-1)
+        ));
+    }
+    Ok(resolution)
+}
+
+fn parse_problem(line: &str, resolution: &mut Resolution) {
+    let line = line.trim();
+    if let Some(target) = line.strip_prefix("error: target not found: ") {
+        resolution.missing.push(target.to_string());
+    } else if line.contains(" are in conflict") || line.contains("unable to satisfy dependency") {
+        resolution
+            .problems
+            .push(line.trim_start_matches(':').trim().to_string());
+    }
+}
+
+/// ### Downloads packages and their dependencies into `cache_dir` with `pacman -Sw`.
+/// Nothing is installed, the packages are picked up from the cache by the installation later.
+/// Transient failures are retried following the retry policy.
+pub fn download(pkgs: &[String], cache_dir: &Path) -> std::io::Result<retry::Output> {
+    let mut command = Cmd::new("pacman");
+    if let Some(conf) = offline::pacman_conf() {
+        command = command.args(["--config", conf]);
+    }
+    command
+        .args(["--dbpath", PLAN_DBPATH])
+        .arg("--cachedir")
+        .arg(cache_dir.to_string_lossy())
+        .args(["-Sw", "--noconfirm"])
+        .args(pkgs.iter().cloned())
+        .status_with_retry()
+}
+
+/// ### Sums the installed size of packages, as shown by `pacman -Si`.
+pub fn installed_size(pkgs: &[String]) -> u64 {
+    if pkgs.is_empty() {
+        return 0;
+    }
+    let mut args = vec!["-Si"];
+    args.extend(pkgs.iter().map(String::as_str));
+    let output = match pacman(&args).output() {
+        Ok(output) => output,
+        Err(e) => {
+            log::warn!("Could not compute installed size: {}", e);
+            return 0;
+        }
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            if key.trim() != "Installed Size" {
+                return None;
+            }
+            parse_size(value.trim())
+        })
+        .sum()
+}
+
+/// Parses sizes such as `12.34 MiB`.
+fn parse_size(size: &str) -> Option<u64> {
+    let (value, unit) = size.split_once(' ')?;
+    let value: f64 = value.parse().ok()?;
+    let factor = match unit {
+        "B" => 1.0,
+        "KiB" => 1024.0,
+        "MiB" => 1024.0 * 1024.0,
+        "GiB" => 1024.0 * 1024.0 * 1024.0,
+        "TiB" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return None,
+    };
+    Some((value * factor) as u64)
+}
+
+/// Formats a size in bytes the way pacman does.
+pub fn human_size(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < units.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.2} {}", size, units[unit])
+}
+
+fn pacman(args: &[&str]) -> Cmd {
+    let mut command = Cmd::new("pacman");
+    if let Some(conf) = offline::pacman_conf() {
+        command = command.args(["--config", conf]);
+    }
+    command
+        .args(["--dbpath", PLAN_DBPATH])
+        .args(args.iter().copied())
+        // Messages are parsed, they must not be translated
+        .env("LC_ALL", "C")
+}
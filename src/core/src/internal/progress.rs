@@ -0,0 +1,284 @@
+// Only the shared progress bar is used when libalpm replaces pacstrap
+#![cfg_attr(feature = "alpm", allow(dead_code))]
+
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use std::io::{BufRead, BufReader, IsTerminal};
+use crate::events::{self, Event, PackagePhase};
+use crate::internal::output::{self, Stream};
+use crate::internal::retry::{self, Output};
+use crate::internal::{exec, runner};
+use std::process::{Command, Stdio};
+
+/// Something that happened in a pacman transaction, parsed from its output.
+#[derive(Debug, PartialEq, Eq)]
+enum PacmanEvent {
+    /// `Packages (N) ...`, the number of packages in the transaction
+    Total(u64),
+    /// ` foo-1.0-1-x86_64 downloading...`
+    Downloading(String),
+    /// `installing foo...`
+    Installing(String),
+    /// `:: Running post-transaction hooks...`
+    HooksStarted,
+    /// `(i/N) Arming ConditionNeedsUpdate...`, only after [`PacmanEvent::HooksStarted`]
+    Hook(u64, u64, String),
+}
+
+/// ### Parses the lines printed by pacman 6 with `--noprogressbar`.
+/// Downloads and installs carry no counter there, they are counted against the
+/// `Packages (N)` total by the caller. Everything before that total, such as the
+/// database sync, is not part of the transaction and ignored.
+#[derive(Default)]
+struct Parser {
+    in_transaction: bool,
+    in_hooks: bool,
+}
+
+impl Parser {
+    fn parse(&mut self, line: &str) -> Option<PacmanEvent> {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("Packages (") {
+            let total = rest.split(')').next()?.parse().ok()?;
+            self.in_transaction = true;
+            return Some(PacmanEvent::Total(total));
+        }
+        if !self.in_transaction {
+            return None;
+        }
+        if let Some(file) = line.strip_suffix(" downloading...") {
+            if file.ends_with(".sig") {
+                return None;
+            }
+            return Some(PacmanEvent::Downloading(file.to_string()));
+        }
+        if line.starts_with(":: Running post-transaction hooks") {
+            self.in_hooks = true;
+            return Some(PacmanEvent::HooksStarted);
+        }
+        if self.in_hooks {
+            let (counter, hook) = line.strip_prefix('(')?.split_once(") ")?;
+            let (current, total) = counter.trim().split_once('/')?;
+            return Some(PacmanEvent::Hook(
+                current.trim().parse().ok()?,
+                total.trim().parse().ok()?,
+                hook.trim_end_matches("...").to_string(),
+            ));
+        }
+        for verb in ["installing ", "upgrading ", "reinstalling ", "downgrading "] {
+            if let Some(package) = line.strip_prefix(verb) {
+                return Some(PacmanEvent::Installing(
+                    package.trim_end_matches("...").to_string(),
+                ));
+            }
+        }
+        None
+    }
+}
+
+/// A progress bar with the style shared by every package backend.
+pub fn bar(multi: &MultiProgress, prefix: &'static str) -> ProgressBar {
+    let bar = multi.add(ProgressBar::new(0));
+    bar.set_style(
+        ProgressStyle::with_template("{prefix:>12.bold} [{bar:30}] {pos}/{len} {wide_msg}")
+            .unwrap()
+            .progress_chars("=> "),
+    );
+    bar.set_prefix(prefix);
+    bar
+}
+
+/// Reports the position of `bar` as a [`Event::Packages`].
+pub fn package_event(phase: PackagePhase, bar: &ProgressBar, name: &str) {
+    events::emit(Event::Packages {
+        phase,
+        current: bar.position(),
+        total: bar.length().unwrap_or(0),
+        name: name.to_string(),
+    });
+}
+
+/// ### Runs `pacstrap` and reports its progress.
+/// - In verbose mode (`-v`), pacman's raw output is shown untouched.
+/// - On a terminal, the output drives progress bars for downloads, installs and hooks.
+/// - Otherwise, every step is logged as a plain line.
+/// - Downloads, installs and hooks are also emitted as [`Event::Packages`].
+///
+/// Errors are shown as they come and returned, see [`retry::classify`].
+pub fn pacstrap(args: &[String]) -> std::io::Result<Output> {
+    let mut argv = vec![String::from("pacstrap")];
+    argv.extend(args.iter().cloned());
+    if !runner::perform(runner::Operation::Command { argv, stdin: None }) {
+        return Ok(Output {
+            status: runner::success(),
+            stderr: String::new(),
+        });
+    }
+    if log::log_enabled!(log::Level::Debug) {
+        return retry::output(Command::new("pacstrap").args(args));
+    }
+
+    let mut child = Command::new("pacstrap")
+        .args(args)
+        .arg("--noprogressbar")
+        // Messages are parsed, they must not be translated
+        .env("LC_ALL", "C")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout = child.stdout.take().unwrap();
+
+    let interactive = std::io::stderr().is_terminal();
+    let multi = MultiProgress::new();
+    let printer = multi.clone();
+    let stderr = exec::tee_stderr(&mut child, move |line| {
+        printer.suspend(|| eprintln!("{}", line))
+    });
+    let downloads = bar(&multi, "Downloading");
+    let installs = bar(&multi, "Installing");
+    let hooks = bar(&multi, "Hooks");
+    if !interactive {
+        multi.set_draw_target(indicatif::ProgressDrawTarget::hidden());
+    }
+
+    let mut parser = Parser::default();
+    for line in BufReader::new(stdout).lines() {
+        let line = line?;
+        output::capture(Stream::Stdout, &line);
+        match parser.parse(&line) {
+            Some(PacmanEvent::Total(total)) => {
+                downloads.set_length(total);
+                installs.set_length(total);
+                log::info!("{} packages to install", total);
+            }
+            Some(PacmanEvent::Downloading(file)) => {
+                downloads.inc(1);
+                downloads.set_message(file.clone());
+                package_event(PackagePhase::Download, &downloads, &file);
+                if !interactive {
+                    log::info!("Downloading {}", file);
+                }
+            }
+            Some(PacmanEvent::Installing(package)) => {
+                downloads.finish_with_message("done");
+                installs.inc(1);
+                installs.set_message(package.clone());
+                package_event(PackagePhase::Install, &installs, &package);
+                if !interactive {
+                    log::info!(
+                        "Installing ({}/{}) {}",
+                        installs.position(),
+                        installs.length().unwrap_or(0),
+                        package
+                    );
+                }
+            }
+            Some(PacmanEvent::HooksStarted) => {
+                installs.finish_with_message("done");
+            }
+            Some(PacmanEvent::Hook(current, total, hook)) => {
+                hooks.set_length(total);
+                hooks.set_position(current);
+                hooks.set_message(hook.clone());
+                package_event(PackagePhase::Hook, &hooks, &hook);
+                if !interactive {
+                    log::info!("Running hook ({}/{}) {}", current, total, hook);
+                }
+            }
+            None => log::trace!("{}", line),
+        }
+    }
+    let status = child.wait()?;
+    hooks.finish_with_message("done");
+    multi.clear()?;
+    let stderr = stderr.and_then(|t| t.join().ok()).unwrap_or_default();
+    Ok(Output { status, stderr })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// What `pacstrap /mnt base nano --noprogressbar` prints with pacman 6.1.
+    const PACSTRAP: &str = "\
+==> Creating install root at /mnt
+==> Installing packages to /mnt
+:: Synchronizing package databases...
+ core downloading...
+ extra downloading...
+resolving dependencies...
+looking for conflicting packages...
+
+Packages (3) filesystem-2024.04.07-1  base-3-2  nano-8.2-1
+
+Total Download Size:    0.71 MiB
+Total Installed Size:   3.02 MiB
+
+:: Proceed with installation? [Y/n] 
+:: Retrieving packages...
+ nano-8.2-1-x86_64 downloading...
+ base-3-2-any downloading...
+checking keyring...
+checking package integrity...
+loading package files...
+checking for file conflicts...
+:: Processing package changes...
+installing filesystem...
+installing base...
+Optional dependencies for base
+    linux: bare metal support
+installing nano...
+:: Running post-transaction hooks...
+(1/2) Reloading system manager configuration...
+  Skipped: Current root is not booted.
+(2/2) Arming ConditionNeedsUpdate...
+";
+
+    fn parse(output: &str) -> Vec<PacmanEvent> {
+        let mut parser = Parser::default();
+        output
+            .lines()
+            .filter_map(|line| parser.parse(line))
+            .collect()
+    }
+
+    #[test]
+    fn parses_a_pacstrap_transaction() {
+        assert_eq!(
+            parse(PACSTRAP),
+            vec![
+                PacmanEvent::Total(3),
+                PacmanEvent::Downloading(String::from("nano-8.2-1-x86_64")),
+                PacmanEvent::Downloading(String::from("base-3-2-any")),
+                PacmanEvent::Installing(String::from("filesystem")),
+                PacmanEvent::Installing(String::from("base")),
+                PacmanEvent::Installing(String::from("nano")),
+                PacmanEvent::HooksStarted,
+                PacmanEvent::Hook(1, 2, String::from("Reloading system manager configuration")),
+                PacmanEvent::Hook(2, 2, String::from("Arming ConditionNeedsUpdate")),
+            ]
+        );
+    }
+
+    #[test]
+    fn database_sync_is_not_a_download() {
+        let events = parse(" core downloading...\n extra downloading...\n");
+        assert!(events.is_empty(), "{:?}", events);
+    }
+
+    #[test]
+    fn upgrades_count_as_installs() {
+        let events = parse(
+            "Packages (2) bash-5.2.037-1  glibc-2.40+r16-1\n\
+             upgrading glibc...\n\
+             reinstalling bash...\n",
+        );
+        assert_eq!(
+            events,
+            vec![
+                PacmanEvent::Total(2),
+                PacmanEvent::Installing(String::from("glibc")),
+                PacmanEvent::Installing(String::from("bash")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,249 @@
+use crate::internal::exec::{self, Cmd};
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::Mutex;
+
+/// ### How often and how long long-running steps are retried.
+/// The delay starts at `backoff` seconds and doubles after each attempt, up to `max_backoff`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    pub attempts: u32,
+    pub backoff: u64,
+    pub max_backoff: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            attempts: 3,
+            backoff: 1,
+            max_backoff: 30,
+        }
+    }
+}
+
+lazy_static! {
+    static ref POLICY: Mutex<RetryPolicy> = Mutex::new(RetryPolicy::default());
+}
+
+pub fn set_policy(policy: RetryPolicy) {
+    log::debug!("Retry policy : {:?}", policy);
+    *POLICY.lock().unwrap() = policy;
+}
+
+/// Why a command failed, guessed from what it printed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Failure {
+    /// Mirror timeouts, DNS or download errors, worth retrying
+    Network,
+    /// Unknown or untrusted keys, retried after refreshing the keyring
+    Signature,
+    /// File or package conflicts, retrying won't help
+    Conflict,
+    DiskFull,
+    Other,
+}
+
+/// The exit status of a command, with what it printed on stderr to classify failures.
+pub struct Output {
+    pub status: ExitStatus,
+    pub stderr: String,
+}
+
+impl From<ExitStatus> for Output {
+    fn from(status: ExitStatus) -> Self {
+        Output {
+            status,
+            stderr: String::new(),
+        }
+    }
+}
+
+/// ### Classifies a failure from the messages of pacman, curl and the coreutils.
+/// They are matched in English, commands whose failures are classified run with `LC_ALL=C`.
+pub fn classify(stderr: &str) -> Failure {
+    let stderr = stderr.to_lowercase();
+    let matches = |patterns: &[&str]| patterns.iter().any(|p| stderr.contains(p));
+    if matches(&["no space left on device", "not enough free disk space"]) {
+        Failure::DiskFull
+    } else if matches(&[
+        "conflicting files",
+        "are in conflict",
+        "conflicting dependencies",
+        "exists in filesystem",
+        "unresolvable package conflicts",
+    ]) {
+        Failure::Conflict
+    } else if matches(&[
+        "pgp signature",
+        "unknown trust",
+        "invalid signature",
+        "unknown public key",
+        "could not be looked up remotely",
+        "keyring is not writable",
+        "required key missing from keyring",
+    ]) {
+        Failure::Signature
+    } else if matches(&[
+        "failed retrieving file",
+        "failed to synchronize",
+        "could not resolve host",
+        "resolving timed out",
+        "connection timed out",
+        "operation too slow",
+        "connection reset",
+        "download library error",
+        "the requested url returned error",
+        "temporary failure in name resolution",
+        "network is unreachable",
+    ]) {
+        Failure::Network
+    } else {
+        Failure::Other
+    }
+}
+
+/// ### Runs `f` until it succeeds, following the retry policy.
+/// The last attempt is returned, with its stderr to report the failure.
+/// - Transient network failures are retried with exponential backoff.
+/// - Signature failures refresh the keyring before the next attempt.
+/// - Conflicts, a full disk and unknown failures are returned right away.
+/// - Errors spawning the command are retried, as they used to be.
+pub fn retry<F>(step: &str, mut f: F) -> std::io::Result<Output>
+where
+    F: FnMut() -> std::io::Result<Output>,
+{
+    let policy = *POLICY.lock().unwrap();
+    let mut delay = policy.backoff;
+    let mut attempt = 1;
+    loop {
+        let last = attempt >= policy.attempts;
+        match f() {
+            Ok(output) if output.status.success() => return Ok(output),
+            Ok(output) => {
+                let failure = classify(&output.stderr);
+                match failure {
+                    Failure::Network if !last => {
+                        log::warn!("{}: network failure, retrying in {}s...", step, delay)
+                    }
+                    Failure::Signature if !last => {
+                        log::warn!("{}: signature failure, refreshing the keyring", step);
+                        refresh_keyring();
+                    }
+                    Failure::Conflict | Failure::DiskFull | Failure::Other => {
+                        log::error!("{}: {:?} failure, not retrying", step, failure);
+                        return Ok(output);
+                    }
+                    _ => return Ok(output),
+                }
+            }
+            Err(e) if !last => log::warn!("{}: {}. Retrying in {}s...", step, e, delay),
+            Err(e) => return Err(e),
+        }
+        std::thread::sleep(std::time::Duration::from_secs(delay));
+        delay = (delay * 2).min(policy.max_backoff);
+        attempt += 1;
+    }
+}
+
+/// ### Refreshes the keyring of the live system and of the target, if it has one.
+/// Runs the commands directly, so that a failure here doesn't retry in turn.
+fn refresh_keyring() {
+    let mut commands = vec![
+        Cmd::new("pacman").args(["-Sy", "--noconfirm", "--needed", "archlinux-keyring"]),
+        Cmd::new("pacman-key").arg("--populate"),
+    ];
+    if std::path::Path::new("/mnt/etc/pacman.d/gnupg/pubring.gpg").exists() {
+        commands.push(Cmd::chroot("pacman-key").arg("--populate"));
+    }
+    for command in commands {
+        let line = command.argv().join(" ");
+        match command.status() {
+            Ok(status) if status.success() => log::info!("{}", line),
+            Ok(status) => log::warn!(
+                "{}  ERROR: exited with code {}",
+                line,
+                status.code().unwrap_or(-1)
+            ),
+            Err(e) => log::warn!("{}  ERROR: {}", line, e),
+        }
+    }
+}
+
+/// ### Runs `command`, showing its output as usual while keeping its stderr.
+/// It runs untranslated, so that its failures can be classified.
+pub fn output(command: &mut Command) -> std::io::Result<Output> {
+    let mut child = command
+        .env("LC_ALL", "C")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout = exec::tee_stdout(&mut child);
+    let stderr = exec::tee_stderr(&mut child, |line| eprintln!("{}", line));
+    let status = child.wait()?;
+    if let Some(stdout) = stdout {
+        let _ = stdout.join();
+    }
+    let stderr = stderr.and_then(|t| t.join().ok()).unwrap_or_default();
+    Ok(Output { status, stderr })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_signature_failures() {
+        for stderr in [
+            "error: linux: signature from \"Jan Alexander Steffens (heftig) <heftig@archlinux.org>\" is unknown trust\n:: File /var/cache/pacman/pkg/linux-6.6.1.arch1-1-x86_64.pkg.tar.zst is corrupted (invalid or corrupted package (PGP signature)).",
+            "error: mesa: key \"8FC15A064950A99DD1BD14DD39E4B877E62EB915\" is unknown\n:: Import PGP key 8FC15A064950A99DD1BD14DD39E4B877E62EB915? [Y/n] error: key \"8FC15A064950A99DD1BD14DD39E4B877E62EB915\" could not be looked up remotely",
+        ] {
+            assert_eq!(classify(stderr), Failure::Signature, "{}", stderr);
+        }
+    }
+
+    #[test]
+    fn classifies_conflicts() {
+        assert_eq!(
+            classify("error: failed to commit transaction (conflicting files)\nnodejs: /mnt/usr/bin/node exists in filesystem\nErrors occurred, no packages were upgraded."),
+            Failure::Conflict
+        );
+        assert_eq!(
+            classify(":: pipewire-jack and jack2 are in conflict. Remove jack2? [y/N]\nerror: unresolvable package conflicts detected\nerror: failed to prepare transaction (conflicting dependencies)"),
+            Failure::Conflict
+        );
+    }
+
+    #[test]
+    fn classifies_a_full_disk() {
+        assert_eq!(
+            classify("error: Partition /mnt too full: 1432586 blocks needed, 1012345 blocks free\nerror: not enough free disk space\nerror: failed to commit transaction (not enough free disk space)"),
+            Failure::DiskFull
+        );
+        assert_eq!(
+            classify("error: could not extract /mnt/usr/lib/libLLVM.so.17 (Write failed: No space left on device)"),
+            Failure::DiskFull
+        );
+    }
+
+    #[test]
+    fn classifies_network_failures() {
+        for stderr in [
+            "error: failed retrieving file 'core.db' from geo.mirror.pkgbuild.com : Could not resolve host: geo.mirror.pkgbuild.com\nerror: failed to synchronize all databases (failed to retrieve some files)",
+            "error: failed retrieving file 'linux-firmware-20231110.74158e7a-1-any.pkg.tar.zst' from mirror.example.org : Operation too slow. Less than 1 bytes/sec transferred the last 10 seconds",
+            "error: failed retrieving file 'extra.db' from mirror.example.org : The requested URL returned error: 404",
+        ] {
+            assert_eq!(classify(stderr), Failure::Network, "{}", stderr);
+        }
+    }
+
+    #[test]
+    fn leaves_unknown_failures_alone() {
+        assert_eq!(
+            classify("error: target not found: linux-zenn"),
+            Failure::Other
+        );
+    }
+}
@@ -0,0 +1,67 @@
+use crate::internal::error::{InstallError, Result};
+use crate::internal::retry;
+
+/// ### Evaluates the result of a command execution.
+///
+/// - If the command succeeds, logs an informational message.
+/// - If the command fails or can't be started, returns an [`InstallError`] with the provided log message,
+///   the exit code and what the command printed on stderr when it was kept.
+///   The target is unmounted by `main` once the error reaches it.
+pub fn exec_eval<O: Into<retry::Output>>(
+    return_code: std::result::Result<O, std::io::Error>,
+    logmsg: &str,
+) -> Result<()> {
+    match return_code.map(Into::into) {
+        Ok(output) => {
+            if output.status.success() {
+                log::info!("{}", logmsg);
+                Ok(())
+            } else {
+                Err(InstallError::Command {
+                    context: logmsg.to_string(),
+                    code: output.status.code(),
+                    stderr: output.stderr,
+                })
+            }
+        }
+        Err(e) => Err(InstallError::io(logmsg, e)),
+    }
+}
+
+/// Evaluates the result of a command execution in a non-fatal way.
+///
+/// - If the command succeeds, logs an informational message.
+/// - If the command fails or returns an error:
+///   - Logs the error, but does not return it (unlike [`exec_eval`]).
+pub fn soft_exec_eval<O: Into<retry::Output>>(
+    return_code: std::result::Result<O, std::io::Error>,
+    logmsg: &str,
+) {
+    match return_code.map(Into::into) {
+        Ok(output) => {
+            if output.status.success() {
+                log::info!("{}", logmsg);
+            } else {
+                log::error!(
+                    "{}  ERROR: exited with code {}",
+                    logmsg,
+                    output.status.code().unwrap_or(-1)
+                );
+            }
+        }
+        Err(e) => {
+            log::error!("{}  ERROR: {}", logmsg, e);
+        }
+    }
+}
+
+/// Evaluates the result of a file operation, errors are returned with the provided log message.
+pub fn files_eval(return_code: std::result::Result<(), std::io::Error>, logmsg: &str) -> Result<()> {
+    match return_code {
+        Ok(_) => {
+            log::info!("{}", logmsg);
+            Ok(())
+        }
+        Err(e) => Err(InstallError::io(logmsg, e)),
+    }
+}
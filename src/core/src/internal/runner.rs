@@ -0,0 +1,226 @@
+use crate::events;
+use crate::internal::output;
+use lazy_static::lazy_static;
+use std::fmt;
+use std::os::unix::process::ExitStatusExt;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::sync::{Arc, Mutex};
+
+/// A side effect of the installation, on the host or in the target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Operation {
+    /// A command, with the full argument vector that is executed and what is written to its stdin
+    Command {
+        argv: Vec<String>,
+        stdin: Option<String>,
+    },
+    /// Packages installed into the target through libalpm
+    Install(Vec<String>),
+    CreateFile(PathBuf),
+    WriteFile { path: PathBuf, contents: String },
+    AppendFile { path: PathBuf, contents: String },
+    ReplaceInFile {
+        path: PathBuf,
+        find: String,
+        replace: String,
+    },
+    CopyFile { from: PathBuf, to: PathBuf },
+    CreateDir(PathBuf),
+    RemoveFile(PathBuf),
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operation::Command { argv, stdin } => {
+                let argv: Vec<String> = argv.iter().map(|arg| quote(arg)).collect();
+                write!(f, "$ {}", argv.join(" "))?;
+                // The input is usually a password, never print it as is
+                for line in stdin.iter().flat_map(|stdin| stdin.lines()) {
+                    write!(f, "\n  < {}", output::redact(line))?;
+                }
+                Ok(())
+            }
+            Operation::Install(packages) => write!(f, "install {}", packages.join(" ")),
+            Operation::CreateFile(path) => write!(f, "create {}", path.display()),
+            Operation::WriteFile { path, contents } => {
+                write!(f, "write {}", path.display())?;
+                write_contents(f, contents)
+            }
+            Operation::AppendFile { path, contents } => {
+                write!(f, "append to {}", path.display())?;
+                write_contents(f, contents)
+            }
+            Operation::ReplaceInFile {
+                path,
+                find,
+                replace,
+            } => write!(
+                f,
+                "replace {} with {} in {}",
+                quote(find),
+                quote(replace),
+                path.display()
+            ),
+            Operation::CopyFile { from, to } => {
+                write!(f, "copy {} to {}", from.display(), to.display())
+            }
+            Operation::CreateDir(path) => write!(f, "mkdir {}", path.display()),
+            Operation::RemoveFile(path) => write!(f, "remove {}", path.display()),
+        }
+    }
+}
+
+fn write_contents(f: &mut fmt::Formatter, contents: &str) -> fmt::Result {
+    for line in contents.lines() {
+        write!(f, "\n  | {}", line)?;
+    }
+    Ok(())
+}
+
+/// Quotes `arg` the way a shell would need it, so that printed commands can be pasted.
+fn quote(arg: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c);
+    if !arg.is_empty() && arg.chars().all(plain) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+/// ### Decides what happens to the side effects of the installation.
+/// Every command and file write goes through [`perform`] first, and is only carried out
+/// when the runner says so. Skipped operations succeed, commands with empty output.
+pub trait Runner: Send {
+    /// Returns whether `operation` should actually be performed.
+    fn perform(&mut self, operation: &Operation) -> bool;
+
+    /// Whether nothing is performed, checks on the target are then skipped as it isn't there.
+    fn simulated(&self) -> bool {
+        true
+    }
+}
+
+/// Performs everything, the default.
+pub struct RealRunner;
+
+impl Runner for RealRunner {
+    fn perform(&mut self, _operation: &Operation) -> bool {
+        true
+    }
+
+    fn simulated(&self) -> bool {
+        false
+    }
+}
+
+/// Prints what the installation would do, without doing it.
+pub struct DryRunner;
+
+impl Runner for DryRunner {
+    fn perform(&mut self, operation: &Operation) -> bool {
+        if events::stdout_reserved() {
+            eprintln!("{}", operation);
+        } else {
+            println!("{}", operation);
+        }
+        false
+    }
+}
+
+/// ### Keeps the sequence of operations instead of performing them.
+/// Clones share the same record, install a clone and inspect the original.
+#[derive(Clone, Default)]
+pub struct RecordingRunner {
+    operations: Arc<Mutex<Vec<Operation>>>,
+}
+
+impl RecordingRunner {
+    pub fn operations(&self) -> Vec<Operation> {
+        self.operations.lock().unwrap().clone()
+    }
+
+    /// Panics with both sequences if the recorded operations are not `expected`.
+    pub fn assert_operations(&self, expected: &[Operation]) {
+        let recorded = self.operations();
+        assert_eq!(
+            recorded, expected,
+            "recorded operations differ from the expected ones"
+        );
+    }
+}
+
+impl Runner for RecordingRunner {
+    fn perform(&mut self, operation: &Operation) -> bool {
+        self.operations.lock().unwrap().push(operation.clone());
+        false
+    }
+}
+
+lazy_static! {
+    static ref RUNNER: Mutex<Box<dyn Runner>> = Mutex::new(Box::new(RealRunner));
+}
+
+pub fn set_runner(runner: Box<dyn Runner>) {
+    *RUNNER.lock().unwrap() = runner;
+}
+
+/// Asks the runner whether `operation` should be performed.
+pub fn perform(operation: Operation) -> bool {
+    RUNNER.lock().unwrap().perform(&operation)
+}
+
+pub fn simulated() -> bool {
+    RUNNER.lock().unwrap().simulated()
+}
+
+/// The exit status of commands that were not run.
+pub fn success() -> ExitStatus {
+    ExitStatus::from_raw(0)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Tests that change or rely on the global runner take it one at a time.
+    static LOCK: Mutex<()> = Mutex::new(());
+
+    /// Runs `f` with a [`RecordingRunner`] installed, one test at a time as the runner is global.
+    pub(crate) fn record(f: impl FnOnce()) -> RecordingRunner {
+        let _lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let recorder = RecordingRunner::default();
+        set_runner(Box::new(recorder.clone()));
+        f();
+        set_runner(Box::new(RealRunner));
+        recorder
+    }
+
+    /// Runs `f` with the [`RealRunner`], without a recording test replacing it meanwhile.
+    pub(crate) fn perform_for_real<T>(f: impl FnOnce() -> T) -> T {
+        let _lock = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        f()
+    }
+
+    /// The command `argv` run in the target, with `stdin` as its input.
+    pub(crate) fn chroot(argv: &[&str], stdin: Option<&str>) -> Operation {
+        Operation::Command {
+            argv: ["arch-chroot", "/mnt"]
+                .iter()
+                .chain(argv)
+                .map(|arg| arg.to_string())
+                .collect(),
+            stdin: stdin.map(String::from),
+        }
+    }
+
+    #[test]
+    fn dry_run_output_hides_the_input() {
+        let operation = chroot(&["chpasswd", "-e"], Some("root:$6$salt$hash\n"));
+        assert_eq!(
+            operation.to_string(),
+            "$ arch-chroot /mnt chpasswd -e\n  < root:[redacted]"
+        );
+    }
+}
@@ -0,0 +1,19 @@
+//! ### The AxOS installer.
+//! An installation is described by an [`InstallPlan`] and run by an [`Installer`],
+//! which reports its progress as [`Event`]s. The single steps are also available
+//! in [`functions`], for front-ends running them one by one like `axinstall-cli`.
+
+pub mod events;
+pub mod functions;
+pub mod internal;
+mod installer;
+mod plan;
+pub mod types;
+
+pub use events::Event;
+pub use installer::{Canceller, Installer, Step};
+pub use internal::error::{InstallError, Result};
+pub use plan::{
+    BaseSystem, Bootloader, InstallPlan, Initramfs, Locale, Network, Offline, Partitioning,
+    Settings, User,
+};
@@ -0,0 +1,131 @@
+//! What to install, as a typed plan instead of a config file.
+
+use crate::functions::pacman::PacmanSettings;
+use crate::internal::exec::ChrootRunner;
+use crate::internal::retry::RetryPolicy;
+use crate::types::{
+    BaseSource, DesktopSetup, GraphicsMode, InitramfsGenerator, Microcode, Partition,
+    PartitionMode, PowerDaemon, UserKit,
+};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// ### Everything an installation does, run by an [`Installer`](crate::Installer).
+/// Built from a config file with [`InstallPlan::from_file`], or directly by front-ends.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstallPlan {
+    pub partitioning: Partitioning,
+    pub base: BaseSystem,
+    pub bootloader: Bootloader,
+    pub locale: Locale,
+    pub network: Network,
+    pub users: Vec<User>,
+    /// Hashed with `openssl passwd -1`
+    pub root_password: String,
+    /// `None` installs no desktop
+    pub desktop: Option<DesktopSetup>,
+    pub power_daemon: PowerDaemon,
+    /// `None` leaves the NVIDIA drivers out
+    pub nvidia: Option<GraphicsMode>,
+    /// The swap file size in MiB, `0` for none
+    pub swap: u64,
+    pub kits: Vec<UserKit>,
+    /// Installed along the kits, an unavailable one doesn't stop the installation
+    pub extra_packages: Vec<String>,
+    pub initramfs: Initramfs,
+    pub settings: Settings,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Partitioning {
+    /// The whole disk, e.g. `/dev/sda`
+    pub device: PathBuf,
+    pub mode: PartitionMode,
+    pub efi: bool,
+    /// Only used by manual partitioning
+    pub partitions: Vec<Partition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BaseSystem {
+    pub source: BaseSource,
+    pub kernels: Vec<String>,
+    pub microcode: Microcode,
+    pub flatpak: bool,
+}
+
+/// GRUB, installed in EFI mode.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bootloader {
+    pub efidir: PathBuf,
+    pub default_kernel: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Locale {
+    pub locales: Vec<String>,
+    pub keymap: String,
+    pub timezone: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Network {
+    pub hostname: String,
+    pub ipv6: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct User {
+    pub name: String,
+    /// Hashed with `openssl passwd -1`
+    pub password: String,
+    pub hasroot: bool,
+    pub shell: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Initramfs {
+    pub generator: InitramfsGenerator,
+    pub hooks: Vec<String>,
+    pub modules: Vec<String>,
+}
+
+/// How the installation reaches packages and runs commands, rather than what it installs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Settings {
+    /// A local repository to install from instead of the mirrors
+    pub offline: Option<Offline>,
+    pub pacman: Option<PacmanSettings>,
+    pub package_cache: Option<PathBuf>,
+    /// Download every package before partitioning
+    pub prefetch: bool,
+    /// Without a package cache, install from the one an earlier `plan --prefetch` filled
+    pub use_prefetched: bool,
+    pub retry: RetryPolicy,
+    pub chroot: ChrootRunner,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Offline {
+    /// Directory or mounted ISO with the repository database and packages
+    pub repository: PathBuf,
+    /// Put the online pacman.conf back in the target once installed
+    pub restore_online: bool,
+}
+
+impl InstallPlan {
+    /// Reads an installation config file, see the README for its format.
+    pub fn from_file<P: Into<PathBuf>>(path: P) -> crate::Result<Self> {
+        crate::internal::config::load(&path.into())
+    }
+
+    /// Reads an installation config given as JSON, in the same format as the files.
+    pub fn from_config(config: serde_json::Value) -> crate::Result<Self> {
+        crate::internal::config::from_value(config)
+    }
+
+    /// The plan as an installation config, the inverse of [`InstallPlan::from_config`].
+    pub fn to_config(&self) -> serde_json::Value {
+        crate::internal::config::to_value(self)
+    }
+}
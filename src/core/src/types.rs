@@ -0,0 +1,122 @@
+//! The choices an installation is made of, shared by the plan and the command line.
+//! With the `clap` feature they can be used as argument values directly.
+
+#[cfg(feature = "clap")]
+use clap::{ArgEnum, Subcommand};
+use serde::{Deserialize, Serialize};
+
+#[cfg_attr(feature = "clap", derive(ArgEnum))]
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Microcode {
+    #[default]
+    Auto,
+    Intel,
+    Amd,
+    None,
+}
+
+#[cfg_attr(feature = "clap", derive(ArgEnum))]
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum InitramfsGenerator {
+    #[default]
+    Mkinitcpio,
+    Dracut,
+}
+
+#[cfg_attr(feature = "clap", derive(ArgEnum))]
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum GraphicsMode {
+    /// Only use the integrated GPU, the NVIDIA one is powered off
+    Integrated,
+    /// Render on the integrated GPU, offload to NVIDIA with `prime-run`
+    #[default]
+    Hybrid,
+    /// Render everything on the NVIDIA GPU
+    Dedicated,
+}
+
+#[cfg_attr(feature = "clap", derive(ArgEnum))]
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum PowerDaemon {
+    /// power-profiles-daemon
+    #[default]
+    Ppd,
+    Tlp,
+    AutoCpufreq,
+}
+
+/// A partition of manual partitioning, formatted and mounted as given.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Partition {
+    pub mountpoint: String,
+    pub blockdevice: String,
+    pub filesystem: String,
+}
+
+impl Partition {
+    pub fn new(mountpoint: String, blockdevice: String, filesystem: String) -> Self {
+        Self {
+            mountpoint,
+            blockdevice,
+            filesystem,
+        }
+    }
+}
+
+#[cfg_attr(feature = "clap", derive(ArgEnum))]
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub enum PartitionMode {
+    Auto,
+    Manual,
+}
+
+/// Where the base system comes from.
+#[cfg_attr(feature = "clap", derive(ArgEnum))]
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BaseSource {
+    /// Install the base packages with pacstrap
+    #[default]
+    Pacstrap,
+    /// Copy the live root filesystem, much faster and fully offline
+    Live,
+}
+
+#[cfg_attr(feature = "clap", derive(ArgEnum))]
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+pub enum DesktopSetup {
+    #[cfg_attr(feature = "clap", clap(aliases = ["plasma"]))]
+    Kde,
+
+    Calla,
+
+    Sleex,
+
+    Theom,
+
+    #[cfg_attr(feature = "clap", clap(name = "None/DIY"))]
+    None,
+}
+
+#[cfg_attr(feature = "clap", derive(Subcommand))]
+#[cfg_attr(
+    feature = "clap",
+    clap(about = "Configure user kits for different profiles.")
+)]
+#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UserKit {
+    Developer,
+
+    Hacker,
+
+    Artist,
+
+    Office,
+
+    Entertainment,
+}
@@ -1,5 +1,8 @@
+use axinstall_core::types::{
+    DesktopSetup, GraphicsMode, InitramfsGenerator, Microcode, Partition, PartitionMode,
+    PowerDaemon, UserKit,
+};
 use clap::{ArgEnum, Args, Parser, Subcommand};
-use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
 #[derive(Debug, Parser)]
@@ -10,6 +13,32 @@ pub struct Opt {
 
     #[clap(long, short, parse(from_occurrences))]
     pub verbose: usize,
+
+    /// Install packages from a local repository (directory or mounted ISO) instead of the mirrors
+    #[clap(long, global = true, value_name = "REPOSITORY")]
+    pub offline: Option<PathBuf>,
+
+    /// Download packages into this directory and install them from there, e.g. /var/cache/pacman/pkg
+    #[clap(long, global = true, value_name = "DIR")]
+    pub package_cache: Option<PathBuf>,
+
+    /// Print the commands and file writes the installation would perform, without performing them
+    #[clap(long, global = true)]
+    pub dry_run: bool,
+
+    /// How progress is reported, `json` prints one event per line for front-ends
+    #[clap(long, global = true, arg_enum, default_value = "human")]
+    pub progress_format: ProgressFormat,
+
+    /// Write the JSON events to this file descriptor instead of stdout
+    #[clap(long, global = true, value_name = "FD")]
+    pub progress_fd: Option<i32>,
+}
+
+#[derive(Debug, ArgEnum, Copy, Clone, PartialEq, Eq)]
+pub enum ProgressFormat {
+    Human,
+    Json,
 }
 
 #[derive(Debug, Subcommand)]
@@ -52,12 +81,21 @@ pub enum Command {
         size: u64,
     },
 
+    /// Copy configuration files from the live system
     #[clap(name = "copy-live-config")]
-    CopyLive,
+    CopyLive {
+        /// Copy the whole live root filesystem first, instead of installing the base with pacstrap
+        #[clap(long)]
+        system: bool,
+    },
 
     /// Install and setup Nvidia drivers
     #[clap(name = "nvidia")]
-    Nvidia,
+    Nvidia {
+        /// Which GPU to use on hybrid (Optimus) laptops
+        #[clap(long, arg_enum, default_value = "hybrid")]
+        mode: GraphicsMode,
+    },
 
     /// Read Axinstall installation config
     #[clap(name = "config")]
@@ -66,6 +104,25 @@ pub enum Command {
         config: PathBuf,
     },
 
+    /// Check that every package of an installation config can be installed, without touching the disk
+    #[clap(name = "plan")]
+    Plan {
+        /// The config file to check
+        config: PathBuf,
+
+        /// Also download every package into the package cache
+        #[clap(long)]
+        prefetch: bool,
+    },
+
+    /// Finish an offline installation, optionally restoring the online pacman.conf in the target
+    #[clap(name = "finish-offline")]
+    FinishOffline {
+        /// Put the live system's online pacman.conf back in the target
+        #[clap(long)]
+        restore_online: bool,
+    },
+
     /// Install a graphical desktop
     #[clap(name = "desktops")]
     Desktops {
@@ -90,6 +147,42 @@ pub enum Command {
     /// Install Flatpak
     #[clap(name = "install-flatpak")]
     InstallFlatpak,
+
+    /// Set up power management, lid and touchpad handling on laptops
+    #[clap(name = "laptop")]
+    Laptop {
+        /// The power management daemon to use
+        #[clap(long, arg_enum, default_value = "ppd")]
+        power_daemon: PowerDaemon,
+    },
+
+    /// Detect the hardware and print the drivers the installer would pick
+    #[clap(name = "detect-hardware")]
+    DetectHardware {
+        /// Print the detection result as JSON
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Write initramfs hooks and modules, then regenerate the initramfs
+    #[clap(name = "initramfs")]
+    Initramfs(InitramfsArgs),
+
+    /// Install interactively, or write the config of the installation, from a wizard in the terminal
+    #[clap(name = "wizard")]
+    Wizard,
+
+    /// Serve the installer over a Unix socket with JSON-RPC, for front-ends running unprivileged
+    #[clap(name = "serve")]
+    Serve {
+        /// The socket to listen on
+        #[clap(long, default_value = "/run/axinstall.sock")]
+        socket: PathBuf,
+
+        /// Let this group connect to the socket, only root can otherwise
+        #[clap(long)]
+        group: Option<String>,
+    },
 }
 
 #[derive(Debug, Args)]
@@ -114,42 +207,43 @@ pub struct PartitionArgs {
 
 #[derive(Debug, Args)]
 pub struct InstallBaseArgs {
-    #[clap(long)]
-    pub kernel: String,
-}
+    /// The kernels to install, can be given several times (e.g. `--kernel linux-zen --kernel linux-lts`)
+    #[clap(long, multiple_occurrences = true)]
+    pub kernel: Vec<String>,
 
-#[derive(Debug)]
-pub struct Partition {
-    pub mountpoint: String,
-    pub blockdevice: String,
-    pub filesystem: String,
+    /// The CPU microcode to install, detected from the running CPU by default
+    #[clap(long, arg_enum, default_value = "auto")]
+    pub microcode: Microcode,
 }
 
-impl Partition {
-    pub fn new(mountpoint: String, blockdevice: String, filesystem: String) -> Self {
-        Self {
-            mountpoint,
-            blockdevice,
-            filesystem,
-        }
-    }
+#[derive(Debug, Args)]
+pub struct InitramfsArgs {
+    /// The tool used to generate the initramfs
+    #[clap(long, arg_enum, default_value = "mkinitcpio")]
+    pub generator: InitramfsGenerator,
+
+    /// Extra mkinitcpio hooks to add, e.g. `encrypt` or `lvm2`
+    #[clap(long, multiple_values = true)]
+    pub hooks: Vec<String>,
+
+    /// Extra kernel modules to load from the initramfs
+    #[clap(long, multiple_values = true)]
+    pub modules: Vec<String>,
 }
 
 pub fn parse_partitions(s: &str) -> Result<Partition, &'static str> {
-    println!("{}", s);
-    Ok(Partition::new(
-        s.split(':').collect::<Vec<&str>>()[0].to_string(),
-        s.split(':').collect::<Vec<&str>>()[1].to_string(),
-        s.split(':').collect::<Vec<&str>>()[2].to_string(),
-    ))
-}
-
-#[derive(Debug, ArgEnum, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
-pub enum PartitionMode {
-    #[clap(name = "auto")]
-    Auto,
-    #[clap(name = "manual")]
-    Manual,
+    match s.split(':').collect::<Vec<&str>>().as_slice() {
+        [mountpoint, blockdevice, filesystem]
+            if !mountpoint.is_empty() && !blockdevice.is_empty() && !filesystem.is_empty() =>
+        {
+            Ok(Partition::new(
+                mountpoint.to_string(),
+                blockdevice.to_string(),
+                filesystem.to_string(),
+            ))
+        }
+        _ => Err("expected mountpoint:blockdevice:filesystem"),
+    }
 }
 
 #[derive(Debug, Subcommand)]
@@ -159,6 +253,10 @@ pub enum BootloaderSubcommand {
     GrubEfi {
         /// The directory to install the EFI bootloader to
         efidir: PathBuf,
+
+        /// The kernel to boot by default when several are installed
+        #[clap(long)]
+        default_kernel: Option<String>,
     },
 
     /// Install GRUB in legacy (BIOS) mode
@@ -191,24 +289,6 @@ pub struct NetworkingArgs {
     pub ipv6: bool,
 }
 
-#[derive(Debug, ArgEnum, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Serialize, Deserialize)]
-pub enum DesktopSetup {
-    #[clap(name = "kde", aliases = ["plasma"])]
-    Kde,
-
-    #[clap(name = "calla")]
-    Calla,
-
-    #[clap(name = "sleex")]
-    Sleex,
-
-    #[clap(name = "theom")]
-    Theom,
-
-    #[clap(name = "None/DIY")]
-    None,
-}
-
 #[derive(Debug, Args)]
 pub struct NewUserArgs {
     /// The name of the user to create
@@ -242,21 +322,19 @@ pub enum UsersSubcommand {
     },
 }
 
-#[derive(Debug, Subcommand)]
-#[clap(about = "Configure user kits for different profiles.")]
-pub enum UserKit {
-    #[clap(name = "developer")]
-    Developer,
-
-    #[clap(name = "hacker")]
-    Hacker,
-
-    #[clap(name = "artist")]
-    Artist,
-
-    #[clap(name = "office")]
-    Office,
-
-    #[clap(name = "entertainment")]
-    Entertainment,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn malformed_partitions_are_rejected() {
+        for value in ["/:/dev/sda2", "/:/dev/sda2:ext4:extra", ":/dev/sda2:ext4", ""] {
+            assert_eq!(
+                parse_partitions(value).err(),
+                Some("expected mountpoint:blockdevice:filesystem"),
+                "{value:?}"
+            );
+        }
+        assert!(parse_partitions("/:/dev/sda2:ext4").is_ok());
+    }
 }
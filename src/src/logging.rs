@@ -1,24 +1,144 @@
-use flexi_logger::{style, DeferredNow, LogSpecification, Logger};
+use axinstall_core::events::{self, Event};
+use axinstall_core::internal::files;
+use axinstall_core::internal::output;
+use flexi_logger::{style, DeferredNow, FileSpec, LogSpecification, Logger, LoggerHandle};
 use log::{LevelFilter};
 use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
+/// Where the log of every run is kept on the live system.
+const LOG_DIR: &str = "/var/log/axinstall";
 
-pub fn init(verbosity: usize) {
-    let log_specification = match verbosity {
-        0 => LogSpecification::builder()
-            .default(LevelFilter::Info)
-            .build(),
-        1 => LogSpecification::builder()
-            .default(LevelFilter::Debug)
-            .build(),
-        _ => LogSpecification::builder()
-            .default(LevelFilter::Trace)
-            .build(),
+/// Where the log is copied in the installed system.
+const TARGET_LOG: &str = "/mnt/var/log/axinstall.log";
+
+/// The log file of this run, if it could be created.
+static LOG_FILE: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// ### Starts the logger, with `forward_warnings` warnings are also sent as [`Event::Warning`].
+/// Everything is also written to `/var/log/axinstall/<timestamp>.log`, with what the commands print.
+pub fn init(verbosity: usize, forward_warnings: bool) {
+    let level = match verbosity {
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
     };
-    Logger::with(log_specification)
+    let log_specification = LogSpecification::builder().default(level).build();
+    let (terminal, terminal_handle) = Logger::with(log_specification.clone())
         .format(format_log_entry)
-        .start()
+        .build()
         .unwrap();
+    let file_spec = FileSpec::default()
+        .directory(LOG_DIR)
+        .basename(timestamp())
+        .suppress_timestamp();
+    let path = file_spec.as_pathbuf(None);
+    let file = Logger::with(log_specification)
+        .log_to_file(file_spec)
+        .format(format_file_entry)
+        .append()
+        .build();
+    let mut handles = vec![terminal_handle];
+    let file = match file {
+        Ok((file, handle)) => {
+            handles.push(handle);
+            *LOG_FILE.lock().unwrap() = Some(path);
+            Some(file)
+        }
+        Err(e) => {
+            eprintln!("Not logging to {LOG_DIR}: {e}");
+            None
+        }
+    };
+    log::set_boxed_logger(Box::new(Forwarding {
+        terminal,
+        file,
+        _handles: handles,
+        forward_warnings,
+    }))
+    .unwrap();
+    log::set_max_level(level);
+}
+
+/// ### Copies the log of this run into the installed system, once the installation is over.
+/// Nothing is copied without a log file, or when the target has no `/var/log`.
+pub fn copy_to_target() {
+    let path = match LOG_FILE.lock().unwrap().clone() {
+        Some(path) => path,
+        None => return,
+    };
+    if !Path::new(TARGET_LOG).parent().unwrap().is_dir() {
+        return;
+    }
+    log::logger().flush();
+    if let Err(e) = files::copy_file(&path.to_string_lossy(), TARGET_LOG) {
+        log::warn!("{}", e);
+    }
+}
+
+/// The local time the run started, e.g. `2024-05-12_14-03-59`.
+fn timestamp() -> String {
+    let mut now = DeferredNow::new();
+    let now = now.now();
+    format!(
+        "{:04}-{:02}-{:02}_{:02}-{:02}-{:02}",
+        now.year(),
+        now.month() as u8,
+        now.day(),
+        now.hour(),
+        now.minute(),
+        now.second()
+    )
+}
+
+/// Logs to the terminal and the log file, and forwards warnings as events.
+/// Secrets are redacted from every record, and the lines captured from commands only go to the file.
+struct Forwarding {
+    terminal: Box<dyn log::Log>,
+    file: Option<Box<dyn log::Log>>,
+    _handles: Vec<LoggerHandle>,
+    forward_warnings: bool,
+}
+
+impl log::Log for Forwarding {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        self.terminal.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        let message = output::redact(&record.args().to_string());
+        if self.forward_warnings && record.level() == log::Level::Warn {
+            events::emit(Event::Warning {
+                message: message.clone(),
+            });
+        }
+        let log = |logger: &dyn log::Log| {
+            logger.log(
+                &log::Record::builder()
+                    .args(format_args!("{}", message))
+                    .level(record.level())
+                    .target(record.target())
+                    .module_path(record.module_path())
+                    .file(record.file())
+                    .line(record.line())
+                    .build(),
+            )
+        };
+        if record.target() != output::TARGET {
+            log(self.terminal.as_ref());
+        }
+        if let Some(file) = &self.file {
+            log(file.as_ref());
+        }
+    }
+
+    fn flush(&self) {
+        self.terminal.flush();
+        if let Some(file) = &self.file {
+            file.flush();
+        }
+    }
 }
 
 /// Formats a log entry with color
@@ -39,4 +159,78 @@ fn format_log_entry(
         s,
         msg
     )
-}
\ No newline at end of file
+}
+
+/// ### Formats a log entry for the log file, without color and with the date.
+/// Escape sequences, from messages or the output of commands, are stripped.
+fn format_file_entry(
+    w: &mut dyn Write,
+    now: &mut DeferredNow,
+    record: &log::Record,
+) -> std::io::Result<()> {
+    let now = now.now();
+    write!(
+        w,
+        "[ {} ] {} {:02}:{:02}:{:02} {}",
+        record.level(),
+        now.date(),
+        now.hour(),
+        now.minute(),
+        now.second(),
+        strip_escapes(&record.args().to_string())
+    )
+}
+
+/// Removes the terminal escape sequences of `text`: colors and other CSI sequences, OSC sequences such as titles.
+fn strip_escapes(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            stripped.push(c);
+            continue;
+        }
+        match chars.next() {
+            // Parameters and intermediate bytes, up to the final byte
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('\x40'..='\x7e').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // Up to BEL or ESC \
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_are_stripped_from_the_file() {
+        assert_eq!(
+            strip_escapes("[ \x1b[2;1;32mOK\x1b[0m ] Read config file"),
+            "[ OK ] Read config file"
+        );
+        assert_eq!(
+            strip_escapes("\x1b]0;pacstrap\x07:: Synchronizing \x1b[1mcore\x1b[0m"),
+            ":: Synchronizing core"
+        );
+        assert_eq!(strip_escapes("plain ünïcode"), "plain ünïcode");
+    }
+}
@@ -1,88 +1,256 @@
 mod args;
-mod functions;
-mod internal;
 mod logging;
+mod serve;
+mod wizard;
 
-use crate::args::{BootloaderSubcommand, Command, Opt, UsersSubcommand};
-use crate::functions::*;
+use crate::args::{BootloaderSubcommand, Command, Opt, ProgressFormat, UsersSubcommand};
+use axinstall_core::events::{self, Event};
+use axinstall_core::functions::*;
+use axinstall_core::types::InitramfsGenerator;
+use axinstall_core::{internal, InstallPlan, Installer, Result};
 use clap::Parser;
+use std::fs::File;
+use std::io::Write;
+use std::os::unix::io::FromRawFd;
+use std::path::PathBuf;
+
+/// The exit code of a Rust program that panicked.
+const PANIC_EXIT_CODE: i32 = 101;
 
 fn main() {
     std::panic::set_hook(Box::new(|info| {
-        println!("Panic occurred: {:?}", info);
+        eprintln!("Panic occurred: {}", info);
+        events::try_emit(Event::Failed {
+            error: format!("Panic occurred: {}", info),
+            exit_code: PANIC_EXIT_CODE,
+        });
     }));
     let opt: Opt = Opt::parse();
-    logging::init(opt.verbose);
+    let json = opt.progress_format == ProgressFormat::Json;
+    let serving = matches!(opt.command, Command::Serve { .. });
+    let installing = matches!(opt.command, Command::Config { .. } | Command::Wizard);
+    // Other commands work on a target mounted beforehand, which is left alone
+    let mounting = installing || matches!(opt.command, Command::Partition(_));
+    logging::init(opt.verbose, json || serving);
+    if json {
+        events::set_listener(json_events(opt.progress_fd));
+    } else {
+        events::set_listener(human_events);
+    }
+    match run(opt) {
+        Ok(()) => {
+            if installing {
+                logging::copy_to_target();
+            }
+            events::emit(Event::Finished)
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            if installing {
+                logging::copy_to_target();
+            }
+            if mounting {
+                unmount_target();
+            }
+            events::emit(Event::Failed {
+                error: e.to_string(),
+                exit_code: e.exit_code(),
+            });
+            std::process::exit(e.exit_code());
+        }
+    }
+}
+
+/// ### Leaves the disk unmounted after a failure, so that the installation can simply be run again.
+/// Only for the commands that mounted the target themselves.
+fn unmount_target() {
+    partition::umount("/mnt/boot/efi");
+    partition::umount("/mnt/");
+}
+
+/// Prints a header when a step starts, the rest is already logged.
+fn human_events(event: &Event) {
+    if let Event::StepStarted {
+        step, index, total, ..
+    } = event
+    {
+        println!();
+        log::info!("[{}/{}] {:?}", index, total, step);
+    }
+}
+
+/// ### Writes every event as a line of JSON, to stdout or to the file descriptor `fd`.
+/// With stdout, the commands print to stderr so that it only carries events.
+fn json_events(fd: Option<i32>) -> impl FnMut(&Event) + Send {
+    let mut out: Box<dyn Write + Send> = match fd {
+        // SAFETY: the descriptor was passed by the front-end for us to write to, and is only used here
+        Some(fd) => Box::new(unsafe { File::from_raw_fd(fd) }),
+        None => {
+            events::reserve_stdout();
+            Box::new(std::io::stdout())
+        }
+    };
+    move |event| {
+        let _ = writeln!(out, "{}", events::to_json(event));
+        let _ = out.flush();
+    }
+}
+
+/// Runs the command, the only caller turning its error into an exit code.
+fn run(opt: Opt) -> Result<()> {
+    if opt.dry_run {
+        internal::runner::set_runner(Box::new(internal::runner::DryRunner));
+    }
+    internal::manifest::check()?;
+    if let Some(repository) = &opt.offline {
+        internal::offline::enable(repository)?;
+    }
+    if let Some(dir) = &opt.package_cache {
+        internal::cache::set_cache_dir(dir)?;
+    }
     match opt.command {
         Command::Partition(args) => {
             let mut partitions = args.partitions;
-            partition::partition(args.device, args.mode, args.efi, &mut partitions);
+            partition::partition(args.device, args.mode, args.efi, &mut partitions)?;
         }
         Command::InstallBase(args) => {
-            base::install_base_packages(args.kernel);
+            base::install_base_packages(args.kernel, args.microcode)?;
         }
         Command::SetupKeyring => {
-            base::setup_archlinux_keyring();
+            base::setup_archlinux_keyring()?;
         }
         Command::GenFstab => {
-            base::genfstab();
+            base::genfstab()?;
         }
         Command::Bootloader { subcommand } => match subcommand {
-            BootloaderSubcommand::GrubEfi { efidir } => {
-                base::install_bootloader_efi(efidir);
+            BootloaderSubcommand::GrubEfi {
+                efidir,
+                default_kernel,
+            } => {
+                base::install_bootloader_efi(efidir, default_kernel)?;
             }
             BootloaderSubcommand::GrubLegacy { device } => {
-                base::install_bootloader_legacy(device);
+                base::install_bootloader_legacy(device)?;
             }
         },
         Command::Locale(args) => {
-            locale::set_locale(args.locales.join(" "));
-            locale::set_keyboard(&args.keyboard);
-            locale::set_timezone(&args.timezone);
+            locale::set_locale(args.locales.join(" "))?;
+            locale::set_keyboard(&args.keyboard)?;
+            locale::set_timezone(&args.timezone)?;
         }
         Command::Networking(args) => {
             if args.ipv6 {
-                network::create_hosts();
-                network::enable_ipv6()
+                network::create_hosts()?;
+                network::enable_ipv6()?;
             } else {
-                network::create_hosts();
+                network::create_hosts()?;
             }
-            network::set_hostname(&args.hostname);
+            network::set_hostname(&args.hostname)?;
         }
         Command::Swap { size } => {
-            base::enable_swap(size);
+            base::enable_swap(size)?;
         }
-        Command::CopyLive => {
-            base::copy_live_config();
+        Command::CopyLive { system } => {
+            if system {
+                live::copy_live_system()?;
+            }
+            base::copy_live_config()?;
+            if system {
+                initramfs::regenerate(InitramfsGenerator::Mkinitcpio)?;
+            }
         }
-        Command::Nvidia => {
-            base::install_nvidia();
+        Command::Nvidia { mode } => {
+            base::install_nvidia(mode)?;
         }
         Command::Config { config } => {
-            crate::internal::config::read_config(config);
+            install(config)?;
+        }
+        Command::Plan { config, prefetch } => {
+            let plan = InstallPlan::from_file(config)?;
+            let prefetch = prefetch || plan.settings.prefetch;
+            let mut installer = Installer::new(plan);
+            installer.check()?;
+            if prefetch {
+                installer.prefetch()?;
+            }
+        }
+        Command::FinishOffline { restore_online } => {
+            internal::offline::finish(restore_online)?;
         }
         Command::Desktops { desktop } => {
-            desktops::install_desktop_setup(desktop);
+            desktops::install_desktop_setup(desktop)?;
         }
         Command::Users { subcommand } => match subcommand {
             UsersSubcommand::NewUser(args) => {
+                internal::output::add_secret(&args.password);
                 users::new_user(
                     &args.username,
                     args.hasroot,
                     &args.password,
                     true,
                     &args.shell,
-                );
+                )?;
             }
             UsersSubcommand::RootPass { password } => {
-                users::root_pass(&password);
+                internal::output::add_secret(&password);
+                users::root_pass(&password)?;
             }
         },
         Command::UserKit { kit } => {
-            userkit::install_userkit(kit);
+            userkit::install_userkit(kit)?;
         }
         Command::InstallFlatpak => {
-            base::install_flatpak();
+            base::install_flatpak()?;
+        }
+        Command::Laptop { power_daemon } => {
+            laptop::setup_laptop(power_daemon)?;
+        }
+        Command::DetectHardware { json } => {
+            let report = hardware::detect();
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report).unwrap());
+            } else {
+                log::info!("CPU vendor : {:?}", report.cpu_vendor);
+                log::info!("Microcode : {:?}", report.microcode);
+                log::info!("Hypervisor : {:?}", report.hypervisor);
+                log::info!("Chassis : {:?}", report.chassis);
+                for gpu in &report.gpus {
+                    log::info!(
+                        "GPU {} [{:04x}:{:04x}] : {:?}",
+                        gpu.device.slot,
+                        gpu.device.vendor,
+                        gpu.device.device,
+                        gpu.driver_stack
+                    );
+                }
+                log::info!("GPU packages : {:?}", report.gpu_packages);
+                log::info!("Blacklist nouveau : {}", report.blacklist_nouveau);
+                log::info!("Hybrid graphics : {}", report.hybrid);
+            }
+        }
+        Command::Initramfs(args) => {
+            for hook in &args.hooks {
+                initramfs::require_hook(hook);
+            }
+            for module in &args.modules {
+                initramfs::require_module(module);
+            }
+            initramfs::regenerate(args.generator)?;
+        }
+        Command::Wizard => {
+            wizard::wizard()?;
+        }
+        Command::Serve { socket, group } => {
+            serve::serve(&socket, group.as_deref())?;
         }
     }
+    Ok(())
+}
+
+/// Installs everything a config file describes.
+fn install(config: PathBuf) -> Result<()> {
+    let plan = InstallPlan::from_file(config)?;
+    Installer::new(plan).run()?;
+    log::info!("Installation finished! You may reboot now!");
+    Ok(())
 }
@@ -0,0 +1,426 @@
+//! ### The installer as a daemon, so that front-ends don't have to run as root.
+//! JSON-RPC 2.0 over a Unix socket, one request, response or notification per line.
+//! Methods:
+//! - `validate_config` and `install` take a config (`path`, or `config` as JSON) and return the steps it enables.
+//! - `list_disks` returns the disks the system can be installed to.
+//! - `subscribe` sends every progress event to the connection, as `progress` notifications.
+//! - `cancel` stops the running installation once its current step is done.
+
+use crate::{logging, unmount_target};
+use axinstall_core::events::{self, Event};
+use axinstall_core::functions::partition;
+use axinstall_core::{Canceller, InstallError, InstallPlan, Installer, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::net::Shutdown;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const PARSE_ERROR: i32 = -32700;
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+/// An installation is already running
+const BUSY: i32 = -32000;
+/// No installation is running
+const NOT_RUNNING: i32 = -32001;
+
+/// A client that doesn't read its messages for this long is dropped, so that it can't stall the others.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(2);
+
+type Connection = Arc<Mutex<UnixStream>>;
+
+/// Connections receiving the progress events.
+static SUBSCRIBERS: Mutex<Vec<Connection>> = Mutex::new(Vec::new());
+/// Cancels the running installation, if any.
+static RUNNING: Mutex<Option<Canceller>> = Mutex::new(None);
+
+#[derive(Deserialize)]
+struct Request {
+    /// Absent for notifications, which get no response
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+/// The config of `validate_config` and `install`.
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ConfigParams {
+    /// A config file, readable by the daemon
+    Path(PathBuf),
+    /// A config in the format of the files
+    Config(Value),
+    /// An [`InstallPlan`] serialized as is
+    Plan(Box<InstallPlan>),
+}
+
+struct RpcError {
+    code: i32,
+    message: String,
+}
+
+impl RpcError {
+    fn new<S: Into<String>>(code: i32, message: S) -> Self {
+        RpcError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// Installation errors keep the exit code of `axinstall-cli`.
+impl From<InstallError> for RpcError {
+    fn from(error: InstallError) -> Self {
+        RpcError::new(error.exit_code(), error.to_string())
+    }
+}
+
+/// ### Listens on `socket` until the process is stopped.
+/// The socket is only accessible to root, and to `group` when given.
+pub fn serve(socket: &Path, group: Option<&str>) -> Result<()> {
+    // Left over by a previous daemon that was stopped
+    if socket.exists() {
+        std::fs::remove_file(socket)
+            .map_err(|e| InstallError::io(format!("Remove stale socket {socket:?}"), e))?;
+    }
+    let listener = UnixListener::bind(socket)
+        .map_err(|e| InstallError::io(format!("Listen on {socket:?}"), e))?;
+    let mode = match group {
+        Some(group) => {
+            let gid = group_id(group)?;
+            std::os::unix::fs::chown(socket, None, Some(gid))
+                .map_err(|e| InstallError::io(format!("Give {socket:?} to group {group}"), e))?;
+            0o660
+        }
+        None => 0o600,
+    };
+    std::fs::set_permissions(socket, std::fs::Permissions::from_mode(mode))
+        .map_err(|e| InstallError::io(format!("Set permissions of {socket:?}"), e))?;
+    events::set_listener(broadcast);
+    log::info!("Listening on {:?}", socket);
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                std::thread::spawn(move || handle_connection(stream));
+            }
+            Err(e) => log::error!("Accept connection  ERROR: {}", e),
+        }
+    }
+    Ok(())
+}
+
+/// Resolves a group name, or a numeric id, from `/etc/group`.
+fn group_id(group: &str) -> Result<u32> {
+    if let Ok(gid) = group.parse() {
+        return Ok(gid);
+    }
+    let groups = std::fs::read_to_string("/etc/group")
+        .map_err(|e| InstallError::io("Read /etc/group", e))?;
+    groups
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<&str>>())
+        .find(|fields| fields.len() > 2 && fields[0] == group)
+        .and_then(|fields| fields[2].parse().ok())
+        .ok_or_else(|| InstallError::invalid(format!("The group {group} doesn't exist")))
+}
+
+fn handle_connection(stream: UnixStream) {
+    let writer = stream
+        .set_write_timeout(Some(WRITE_TIMEOUT))
+        .and_then(|()| stream.try_clone());
+    let connection: Connection = match writer {
+        Ok(writer) => Arc::new(Mutex::new(writer)),
+        Err(e) => {
+            log::error!("Accept connection  ERROR: {}", e);
+            return;
+        }
+    };
+    for line in BufReader::new(stream)
+        .lines()
+        .map_while(std::io::Result::ok)
+    {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => {
+                log::debug!("Request {} {}", request.method, request.params);
+                let result = call(&request.method, request.params, &connection);
+                match request.id {
+                    Some(id) => response(id, result),
+                    None => continue,
+                }
+            }
+            Err(e) => response(Value::Null, Err(RpcError::new(PARSE_ERROR, e.to_string()))),
+        };
+        if send(&connection, &response).is_err() {
+            break;
+        }
+    }
+}
+
+fn call(
+    method: &str,
+    params: Value,
+    connection: &Connection,
+) -> std::result::Result<Value, RpcError> {
+    match method {
+        "validate_config" => {
+            let installer = Installer::new(plan(params)?);
+            Ok(json!({ "steps": installer.steps() }))
+        }
+        "list_disks" => Ok(json!(partition::list_disks())),
+        "install" => {
+            let mut installer = Installer::new(plan(params)?);
+            let mut running = RUNNING.lock().unwrap();
+            if running.is_some() {
+                return Err(RpcError::new(BUSY, "An installation is already running"));
+            }
+            *running = Some(installer.canceller());
+            let steps = installer.steps();
+            std::thread::spawn(move || {
+                let result = installer.run();
+                *RUNNING.lock().unwrap() = None;
+                finish(result);
+            });
+            Ok(json!({ "steps": steps }))
+        }
+        "subscribe" => {
+            SUBSCRIBERS.lock().unwrap().push(connection.clone());
+            Ok(json!(true))
+        }
+        "cancel" => match RUNNING.lock().unwrap().as_ref() {
+            Some(canceller) => {
+                log::info!("Cancelling the installation");
+                canceller.cancel();
+                Ok(json!(true))
+            }
+            None => Err(RpcError::new(NOT_RUNNING, "No installation is running")),
+        },
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("Unknown method {method}"),
+        )),
+    }
+}
+
+fn plan(params: Value) -> std::result::Result<InstallPlan, RpcError> {
+    let params: ConfigParams = serde_json::from_value(params).map_err(|e| {
+        RpcError::new(
+            INVALID_PARAMS,
+            format!("Expected one of path, config or plan  ERROR: {}", e),
+        )
+    })?;
+    Ok(match params {
+        ConfigParams::Path(path) => InstallPlan::from_file(path)?,
+        ConfigParams::Config(config) => InstallPlan::from_config(config)?,
+        ConfigParams::Plan(plan) => *plan,
+    })
+}
+
+/// Reports the end of an installation, the way `main` does for the command line.
+fn finish(result: Result<()>) {
+    match result {
+        Ok(()) => {
+            log::info!("Installation finished! You may reboot now!");
+            logging::copy_to_target();
+            events::emit(Event::Finished);
+        }
+        Err(e) => {
+            log::error!("{}", e);
+            logging::copy_to_target();
+            unmount_target();
+            events::emit(Event::Failed {
+                error: e.to_string(),
+                exit_code: e.exit_code(),
+            });
+        }
+    }
+}
+
+fn response(id: Value, result: std::result::Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err(error) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": error.code, "message": error.message },
+        }),
+    }
+}
+
+fn send(connection: &Connection, message: &Value) -> std::io::Result<()> {
+    let mut stream = connection.lock().unwrap();
+    writeln!(stream, "{}", message)?;
+    stream.flush()
+}
+
+/// ### Sends `event` to every subscriber, forgetting the ones that are gone or too slow.
+/// A slow subscriber may have been sent part of a line, so its connection is closed.
+/// Nothing is logged above debug here, warnings are sent back as events.
+fn broadcast(event: &Event) {
+    let notification = json!({
+        "jsonrpc": "2.0",
+        "method": "progress",
+        "params": events::to_value(event),
+    });
+    SUBSCRIBERS.lock().unwrap().retain(|connection| {
+        let sent = send(connection, &notification).is_ok();
+        if !sent {
+            log::debug!("Dropping a subscriber that went away or stopped reading");
+            let _ = connection.lock().unwrap().shutdown(Shutdown::Both);
+        }
+        sent
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axinstall_core::internal::runner::{self, DryRunner};
+
+    /// A front-end connected to the socket, keeping the notifications it receives.
+    struct Client {
+        writer: UnixStream,
+        reader: BufReader<UnixStream>,
+        next_id: u64,
+        notifications: Vec<Value>,
+    }
+
+    impl Client {
+        fn connect(socket: &Path) -> Client {
+            // The daemon is started from another thread
+            for _ in 0..50 {
+                if let Ok(stream) = UnixStream::connect(socket) {
+                    return Client {
+                        writer: stream.try_clone().unwrap(),
+                        reader: BufReader::new(stream),
+                        next_id: 0,
+                        notifications: Vec::new(),
+                    };
+                }
+                std::thread::sleep(Duration::from_millis(100));
+            }
+            panic!("The daemon doesn't listen on {socket:?}");
+        }
+
+        fn read(&mut self) -> Value {
+            let mut line = String::new();
+            self.reader.read_line(&mut line).unwrap();
+            serde_json::from_str(&line).unwrap()
+        }
+
+        /// Calls `method` and returns its response, keeping the notifications sent meanwhile.
+        fn call(&mut self, method: &str, params: Value) -> Value {
+            self.next_id += 1;
+            let request = json!({
+                "jsonrpc": "2.0",
+                "id": self.next_id,
+                "method": method,
+                "params": params,
+            });
+            writeln!(self.writer, "{}", request).unwrap();
+            loop {
+                let message = self.read();
+                if message["id"] == json!(self.next_id) {
+                    return message;
+                }
+                self.notifications.push(message);
+            }
+        }
+
+        /// Reads the progress events up to the last one, `finished` or `failed`.
+        fn events(&mut self) -> Vec<Value> {
+            let mut events: Vec<Value> = Vec::new();
+            for notification in std::mem::take(&mut self.notifications) {
+                events.push(notification["params"].clone());
+            }
+            while !events
+                .last()
+                .is_some_and(|event| event["event"] == "finished" || event["event"] == "failed")
+            {
+                let notification = self.read();
+                events.push(notification["params"].clone());
+            }
+            events
+        }
+    }
+
+    fn config() -> Value {
+        json!({
+            "partition": { "device": "null", "mode": "Auto", "efi": true, "partitions": [] },
+            "bootloader": { "type": "grub-efi", "location": "/boot/efi" },
+            "locale": { "locale": ["en_US.UTF-8 UTF-8"], "keymap": "us", "timezone": "Europe/Paris" },
+            "networking": { "hostname": "axos", "ipv6": false },
+            "users": [{ "name": "alice", "password": "$6$salt$hash", "hasroot": true, "shell": "bash" }],
+            "rootpass": "$6$salt$hash",
+            "desktop": "kde",
+            "swap": 0,
+            "nvidia": false,
+            "extra_packages": [],
+            "kernel": "linux",
+            "artist_uk": false,
+            "devel_uk": false,
+            "hacker_uk": false,
+            "office_uk": false,
+            "entertainment_uk": false,
+            "flatpak": false
+        })
+    }
+
+    #[test]
+    fn serves_a_dry_run_installation() {
+        runner::set_runner(Box::new(DryRunner));
+        let dir = std::env::temp_dir().join(format!("axinstall-serve-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket = dir.join("axinstall.sock");
+        let path = socket.clone();
+        std::thread::spawn(move || serve(&path, None));
+        let mut client = Client::connect(&socket);
+
+        let steps = client.call("validate_config", json!({ "config": config() }));
+        assert_eq!(steps["result"]["steps"][0], "partition", "{steps}");
+        let invalid = client.call(
+            "validate_config",
+            json!({ "path": dir.join("missing.json") }),
+        );
+        assert!(invalid["error"]["code"].is_i64(), "{invalid}");
+
+        let disks = client.call("list_disks", Value::Null);
+        assert!(disks["result"].is_array(), "{disks}");
+
+        let cancel = client.call("cancel", Value::Null);
+        assert_eq!(cancel["error"]["code"], NOT_RUNNING, "{cancel}");
+
+        assert_eq!(client.call("subscribe", Value::Null)["result"], true);
+        let install = client.call("install", json!({ "config": config() }));
+        assert_eq!(install["result"]["steps"], steps["result"]["steps"]);
+        let busy = client.call("install", json!({ "config": config() }));
+        let cancel = client.call("cancel", Value::Null);
+        let events = client.events();
+        let last = events.last().unwrap();
+        // The installation may be over before it could be cancelled
+        if cancel["result"] == true {
+            assert_eq!(busy["error"]["code"], BUSY, "{busy}");
+            assert_eq!(last["event"], "failed", "{last}");
+            assert_eq!(last["exit_code"], InstallError::Cancelled.exit_code());
+        } else {
+            assert_eq!(cancel["error"]["code"], NOT_RUNNING, "{cancel}");
+            assert_eq!(last["event"], "finished", "{last}");
+            let started = events
+                .iter()
+                .filter(|event| event["event"] == "step-started");
+            assert_eq!(
+                started.count(),
+                steps["result"]["steps"].as_array().unwrap().len()
+            );
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,680 @@
+//! The pages and fields of the wizard, with their live validation.
+
+use axinstall_core::functions::hardware::{self, Firmware};
+use axinstall_core::functions::{locale, partition};
+use axinstall_core::types::DesktopSetup;
+use clap::ArgEnum;
+
+/// Identifies a field, to read its value back when building the plan.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Id {
+    Disk,
+    Mode,
+    Efi,
+    Partitions,
+    Swap,
+    EfiDir,
+    Kernels,
+    DefaultKernel,
+    Locale,
+    Keymap,
+    Timezone,
+    Hostname,
+    Ipv6,
+    Username,
+    Password,
+    ConfirmPassword,
+    Sudo,
+    Shell,
+    AnotherUser,
+    RootPassword,
+    ConfirmRootPassword,
+    Desktop,
+    Nvidia,
+    Kits,
+    Flatpak,
+    ExtraPackages,
+    Action,
+    Output,
+}
+
+pub enum Kind {
+    /// Free text, `secret` text is shown as stars
+    Text {
+        value: String,
+        secret: bool,
+    },
+    Toggle(bool),
+    /// One of the `(value, label)` options, filtered by what is typed.
+    /// Without options, e.g. when the system has no list of them, the typed text is the value.
+    Choice {
+        options: Vec<(String, String)>,
+        filter: String,
+        cursor: usize,
+    },
+    /// Any number of the options
+    Multi {
+        options: Vec<String>,
+        checked: Vec<bool>,
+        cursor: usize,
+    },
+}
+
+pub struct Field {
+    pub id: Id,
+    pub label: &'static str,
+    pub kind: Kind,
+    /// Errors are only shown once the field was edited, or the page was submitted
+    pub touched: bool,
+}
+
+pub struct Page {
+    pub title: &'static str,
+    pub fields: Vec<Field>,
+}
+
+/// A user submitted on the users page, before the one being typed.
+pub struct NewUser {
+    pub name: String,
+    pub password: String,
+    pub sudo: bool,
+    pub shell: String,
+}
+
+pub struct Form {
+    pub pages: Vec<Page>,
+    pub page: usize,
+    pub focus: usize,
+    /// The users added so far, the fields of the users page hold the last one
+    pub users: Vec<NewUser>,
+}
+
+impl Field {
+    fn new(id: Id, label: &'static str, kind: Kind) -> Self {
+        Field {
+            id,
+            label,
+            kind,
+            touched: false,
+        }
+    }
+
+    fn text(id: Id, label: &'static str, value: &str) -> Self {
+        Field::new(
+            id,
+            label,
+            Kind::Text {
+                value: value.to_string(),
+                secret: false,
+            },
+        )
+    }
+
+    fn secret(id: Id, label: &'static str) -> Self {
+        Field::new(
+            id,
+            label,
+            Kind::Text {
+                value: String::new(),
+                secret: true,
+            },
+        )
+    }
+
+    fn choice(id: Id, label: &'static str, options: Vec<String>, default: &str) -> Self {
+        let options: Vec<(String, String)> = options.into_iter().map(|o| (o.clone(), o)).collect();
+        Field::labelled_choice(id, label, options, default)
+    }
+
+    fn labelled_choice(
+        id: Id,
+        label: &'static str,
+        options: Vec<(String, String)>,
+        default: &str,
+    ) -> Self {
+        let cursor = options
+            .iter()
+            .position(|(value, _)| value == default)
+            .unwrap_or(0);
+        // Free text fields start with the default instead
+        let filter = if options.is_empty() {
+            default.to_string()
+        } else {
+            String::new()
+        };
+        Field::new(
+            id,
+            label,
+            Kind::Choice {
+                options,
+                filter,
+                cursor,
+            },
+        )
+    }
+
+    /// The options of a list field matching what was typed, as `(value, label)`.
+    pub fn matches(&self) -> Vec<(&str, &str)> {
+        match &self.kind {
+            Kind::Choice {
+                options, filter, ..
+            } => {
+                let filter = filter.to_lowercase();
+                options
+                    .iter()
+                    .filter(|(_, label)| label.to_lowercase().contains(&filter))
+                    .map(|(value, label)| (value.as_str(), label.as_str()))
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// The value as text, `None` when a choice has no match.
+    pub fn value(&self) -> Option<String> {
+        match &self.kind {
+            Kind::Text { value, .. } => Some(value.trim().to_string()),
+            Kind::Toggle(on) => Some(String::from(if *on { "yes" } else { "no" })),
+            Kind::Choice {
+                options,
+                filter,
+                cursor,
+            } => {
+                if options.is_empty() {
+                    Some(filter.trim().to_string())
+                } else {
+                    self.matches()
+                        .get(*cursor)
+                        .map(|(value, _)| value.to_string())
+                }
+            }
+            Kind::Multi { .. } => Some(self.checked().join(", ")),
+        }
+    }
+
+    /// The value as shown in the summary, secrets hidden.
+    pub fn display(&self) -> String {
+        match &self.kind {
+            Kind::Text {
+                value,
+                secret: true,
+            } => "*".repeat(value.chars().count()),
+            Kind::Choice { .. } => {
+                let value = self.value().unwrap_or_default();
+                self.matches()
+                    .into_iter()
+                    .find(|(v, _)| *v == value)
+                    .map(|(_, label)| label.to_string())
+                    .unwrap_or(value)
+            }
+            _ => self.value().unwrap_or_default(),
+        }
+    }
+
+    pub fn checked(&self) -> Vec<String> {
+        match &self.kind {
+            Kind::Multi {
+                options, checked, ..
+            } => options
+                .iter()
+                .zip(checked)
+                .filter(|(_, checked)| **checked)
+                .map(|(option, _)| option.clone())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn is_list(&self) -> bool {
+        match &self.kind {
+            Kind::Choice { options, .. } => !options.is_empty(),
+            Kind::Multi { .. } => true,
+            _ => false,
+        }
+    }
+
+    fn push(&mut self, c: char) {
+        match &mut self.kind {
+            Kind::Text { value, .. } => value.push(c),
+            Kind::Toggle(on) if c == ' ' => *on = !*on,
+            Kind::Choice { filter, cursor, .. } => {
+                filter.push(c);
+                *cursor = 0;
+            }
+            Kind::Multi {
+                checked, cursor, ..
+            } if c == ' ' => {
+                if let Some(checked) = checked.get_mut(*cursor) {
+                    *checked = !*checked;
+                }
+            }
+            _ => return,
+        }
+        self.touched = true;
+    }
+
+    fn pop(&mut self) {
+        match &mut self.kind {
+            Kind::Text { value, .. } => {
+                value.pop();
+            }
+            Kind::Choice { filter, cursor, .. } => {
+                filter.pop();
+                *cursor = 0;
+            }
+            _ => return,
+        }
+        self.touched = true;
+    }
+
+    fn scroll(&mut self, up: bool) {
+        let len = match &self.kind {
+            Kind::Choice { .. } => self.matches().len(),
+            Kind::Multi { options, .. } => options.len(),
+            _ => return,
+        };
+        if let Kind::Choice { cursor, .. } | Kind::Multi { cursor, .. } = &mut self.kind {
+            if up {
+                *cursor = cursor.saturating_sub(1);
+            } else if *cursor + 1 < len {
+                *cursor += 1;
+            }
+        }
+        self.touched = true;
+    }
+}
+
+impl Form {
+    /// The pages of the wizard, with the lists and defaults read from the running system.
+    pub fn new() -> Self {
+        let disks = partition::list_disks()
+            .into_iter()
+            .map(|disk| {
+                let path = disk.path.to_string_lossy().to_string();
+                let mut label = format!(
+                    "{}  {:.1} GiB",
+                    path,
+                    disk.size as f64 / (1024 * 1024 * 1024) as f64
+                );
+                if let Some(model) = disk.model {
+                    label.push_str(&format!("  {model}"));
+                }
+                if disk.removable {
+                    label.push_str("  (removable)");
+                }
+                (path, label)
+            })
+            .collect();
+        let desktops = DesktopSetup::value_variants()
+            .iter()
+            .filter_map(|desktop| desktop.to_possible_value())
+            .map(|value| value.get_name().to_string())
+            .collect();
+        let kits: Vec<String> = ["developer", "hacker", "artist", "office", "entertainment"]
+            .iter()
+            .map(|kit| kit.to_string())
+            .collect();
+        let report = hardware::detect();
+        let pages = vec![
+            Page {
+                title: "Disk",
+                fields: vec![Field::labelled_choice(Id::Disk, "Install to", disks, "")],
+            },
+            Page {
+                title: "Partitioning",
+                fields: vec![
+                    Field::choice(Id::Mode, "Mode", strings(&["auto", "manual"]), "auto"),
+                    Field::new(
+                        Id::Efi,
+                        "EFI",
+                        Kind::Toggle(hardware::detect_firmware() == Firmware::Uefi),
+                    ),
+                    Field::text(
+                        Id::Partitions,
+                        "Partitions (manual, mountpoint:blockdevice:filesystem ...)",
+                        "",
+                    ),
+                    Field::text(Id::Swap, "Swap file in MiB (0 for none)", "0"),
+                ],
+            },
+            Page {
+                title: "Bootloader",
+                fields: vec![
+                    Field::text(Id::EfiDir, "EFI directory", "/boot/efi"),
+                    Field::text(Id::Kernels, "Kernels (empty for the default)", ""),
+                    Field::text(
+                        Id::DefaultKernel,
+                        "Default kernel (with several, empty for the first)",
+                        "",
+                    ),
+                ],
+            },
+            Page {
+                title: "Locale",
+                fields: vec![
+                    Field::choice(
+                        Id::Locale,
+                        "Locale",
+                        locale::supported_locales(),
+                        "en_US.UTF-8 UTF-8",
+                    ),
+                    Field::choice(Id::Keymap, "Keymap", locale::keymaps(), "us"),
+                    Field::choice(Id::Timezone, "Timezone", locale::timezones(), "UTC"),
+                ],
+            },
+            Page {
+                title: "Network",
+                fields: vec![
+                    Field::text(Id::Hostname, "Hostname", "axos"),
+                    Field::new(Id::Ipv6, "IPv6", Kind::Toggle(false)),
+                ],
+            },
+            Page {
+                title: "Users",
+                fields: vec![
+                    Field::text(Id::Username, "Username", ""),
+                    Field::secret(Id::Password, "Password"),
+                    Field::secret(Id::ConfirmPassword, "Confirm password"),
+                    Field::new(Id::Sudo, "Administrator (sudo)", Kind::Toggle(true)),
+                    Field::choice(
+                        Id::Shell,
+                        "Shell",
+                        strings(&["bash", "csh", "fish", "tcsh", "zsh"]),
+                        "fish",
+                    ),
+                    Field::new(
+                        Id::AnotherUser,
+                        "Add another user after this one",
+                        Kind::Toggle(false),
+                    ),
+                ],
+            },
+            Page {
+                title: "Root",
+                fields: vec![
+                    Field::secret(Id::RootPassword, "Root password"),
+                    Field::secret(Id::ConfirmRootPassword, "Confirm root password"),
+                ],
+            },
+            Page {
+                title: "Desktop",
+                fields: vec![
+                    Field::choice(Id::Desktop, "Desktop", desktops, "kde"),
+                    Field::new(
+                        Id::Nvidia,
+                        "NVIDIA drivers",
+                        Kind::Toggle(report.blacklist_nouveau),
+                    ),
+                ],
+            },
+            Page {
+                title: "Software",
+                fields: vec![
+                    Field::new(
+                        Id::Kits,
+                        "User kits",
+                        Kind::Multi {
+                            checked: vec![false; kits.len()],
+                            options: kits,
+                            cursor: 0,
+                        },
+                    ),
+                    Field::new(Id::Flatpak, "Flatpak", Kind::Toggle(true)),
+                    Field::text(Id::ExtraPackages, "Extra packages", ""),
+                ],
+            },
+            Page {
+                title: "Summary",
+                fields: vec![
+                    Field::choice(
+                        Id::Action,
+                        "Then",
+                        strings(&["install", "write config"]),
+                        "install",
+                    ),
+                    Field::text(Id::Output, "Config file", "axinstall.json"),
+                ],
+            },
+        ];
+        Form {
+            pages,
+            page: 0,
+            focus: 0,
+            users: Vec::new(),
+        }
+    }
+
+    fn field_mut(&mut self, id: Id) -> &mut Field {
+        self.pages
+            .iter_mut()
+            .flat_map(|page| &mut page.fields)
+            .find(|field| field.id == id)
+            .unwrap()
+    }
+
+    pub fn field(&self, id: Id) -> &Field {
+        self.pages
+            .iter()
+            .flat_map(|page| &page.fields)
+            .find(|field| field.id == id)
+            .unwrap()
+    }
+
+    /// The value of a field, empty for a choice without match, which doesn't validate anyway.
+    pub fn value(&self, id: Id) -> String {
+        self.field(id).value().unwrap_or_default()
+    }
+
+    pub fn toggle(&self, id: Id) -> bool {
+        matches!(self.field(id).kind, Kind::Toggle(true))
+    }
+
+    pub fn is_last_page(&self) -> bool {
+        self.page + 1 == self.pages.len()
+    }
+
+    /// ### Checks a field, returning the message to show under it.
+    /// Some fields depend on others, e.g. the partitions are only needed for manual partitioning.
+    pub fn check(&self, id: Id) -> Result<(), String> {
+        let field = self.field(id);
+        let value = field
+            .value()
+            .ok_or_else(|| String::from("Nothing matches, pick one from the list"))?;
+        match id {
+            Id::Disk if !value.starts_with("/dev/") => {
+                Err(String::from("Pick a disk, e.g. /dev/sda"))
+            }
+            Id::Partitions if self.value(Id::Mode) == "manual" => check_partitions(&value),
+            Id::EfiDir if !value.starts_with('/') => Err(String::from("Must be an absolute path")),
+            Id::Swap if value.parse::<u64>().is_err() => {
+                Err(String::from("A size in MiB, e.g. 4096"))
+            }
+            Id::Kernels | Id::ExtraPackages => check_packages(&value),
+            Id::DefaultKernel
+                if !value.is_empty()
+                    && !self
+                        .value(Id::Kernels)
+                        .split_whitespace()
+                        .any(|k| k == value) =>
+            {
+                Err(String::from("Must be one of the kernels above"))
+            }
+            Id::Locale | Id::Keymap | Id::Timezone | Id::Output if value.is_empty() => {
+                Err(String::from("Required"))
+            }
+            Id::Hostname => check_hostname(&value),
+            Id::Username if self.users.iter().any(|user| user.name == value) => {
+                Err(String::from("This user was already added"))
+            }
+            Id::Username => check_username(&value),
+            Id::Password | Id::RootPassword if value.is_empty() => Err(String::from("Required")),
+            Id::ConfirmPassword if value != self.value(Id::Password) => {
+                Err(String::from("The passwords don't match"))
+            }
+            Id::ConfirmRootPassword if value != self.value(Id::RootPassword) => {
+                Err(String::from("The passwords don't match"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Marks every field of the page as touched, true if they are all valid.
+    fn submit(&mut self) -> bool {
+        for field in &mut self.pages[self.page].fields {
+            field.touched = true;
+        }
+        self.pages[self.page]
+            .fields
+            .iter()
+            .all(|field| self.check(field.id).is_ok())
+    }
+
+    fn focused(&mut self) -> &mut Field {
+        &mut self.pages[self.page].fields[self.focus]
+    }
+
+    /// ### Moves to the next page if the current one is valid.
+    /// Returns true when the last page was submitted.
+    /// A user submitted with another one to come is kept, and the users page starts over.
+    pub fn next(&mut self) -> bool {
+        if !self.submit() {
+            return false;
+        }
+        let on_users_page = self.pages[self.page]
+            .fields
+            .iter()
+            .any(|field| field.id == Id::AnotherUser);
+        if on_users_page && self.toggle(Id::AnotherUser) {
+            self.users.push(NewUser {
+                name: self.value(Id::Username),
+                password: self.value(Id::Password),
+                sudo: self.toggle(Id::Sudo),
+                shell: self.value(Id::Shell),
+            });
+            for id in [Id::Username, Id::Password, Id::ConfirmPassword] {
+                let field = self.field_mut(id);
+                if let Kind::Text { value, .. } = &mut field.kind {
+                    value.clear();
+                }
+                field.touched = false;
+            }
+            self.field_mut(Id::AnotherUser).kind = Kind::Toggle(false);
+            self.focus = 0;
+            return false;
+        }
+        if self.is_last_page() {
+            return true;
+        }
+        self.page += 1;
+        self.focus = 0;
+        false
+    }
+
+    pub fn back(&mut self) {
+        if self.page > 0 {
+            self.page -= 1;
+            self.focus = 0;
+        }
+    }
+
+    pub fn focus_next(&mut self, forward: bool) {
+        let len = self.pages[self.page].fields.len();
+        self.focus = if forward {
+            (self.focus + 1) % len
+        } else {
+            (self.focus + len - 1) % len
+        };
+    }
+
+    pub fn type_char(&mut self, c: char) {
+        self.focused().push(c);
+    }
+
+    pub fn backspace(&mut self) {
+        self.focused().pop();
+    }
+
+    /// Moves in the list of the focused field, or to the previous or next field.
+    pub fn scroll(&mut self, up: bool) {
+        if self.focused().is_list() {
+            self.focused().scroll(up);
+        } else {
+            self.focus_next(!up);
+        }
+    }
+}
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+fn check_partitions(value: &str) -> Result<(), String> {
+    if value.is_empty() {
+        return Err(String::from("Required for manual partitioning"));
+    }
+    for partition in value.split_whitespace() {
+        let fields: Vec<&str> = partition.split(':').collect();
+        match fields.as_slice() {
+            [mountpoint, blockdevice, filesystem]
+                if mountpoint.starts_with('/')
+                    && blockdevice.starts_with("/dev/")
+                    && !filesystem.is_empty() => {}
+            _ => {
+                return Err(format!(
+                    "{partition:?} must be mountpoint:blockdevice:filesystem, e.g. /:/dev/sda2:ext4"
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+fn check_packages(value: &str) -> Result<(), String> {
+    let valid = |name: &str| {
+        name.chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "@._+-".contains(c))
+            && !name.starts_with(['-', '.'])
+    };
+    match value.split_whitespace().find(|name| !valid(name)) {
+        Some(name) => Err(format!("{name:?} is not a valid package name")),
+        None => Ok(()),
+    }
+}
+
+/// A hostname of RFC 1123, labels of up to 63 letters, digits and hyphens.
+fn check_hostname(value: &str) -> Result<(), String> {
+    let valid_label = |label: &str| {
+        !label.is_empty()
+            && label.len() <= 63
+            && !label.starts_with('-')
+            && !label.ends_with('-')
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    };
+    if value.is_empty() {
+        Err(String::from("Required"))
+    } else if value.len() > 253 || !value.split('.').all(valid_label) {
+        Err(String::from(
+            "Only letters, digits and hyphens, not at the start or end",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// A name `useradd` accepts, lowercase and up to 32 characters.
+fn check_username(value: &str) -> Result<(), String> {
+    let mut chars = value.chars();
+    let valid = matches!(chars.next(), Some(c) if c.is_ascii_lowercase() || c == '_')
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-');
+    if value.is_empty() {
+        Err(String::from("Required"))
+    } else if value == "root" {
+        Err(String::from("root already exists"))
+    } else if value.len() > 32 || !valid {
+        Err(String::from(
+            "Lowercase letters, digits, - and _, starting with a letter, up to 32",
+        ))
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+//! ### An interactive installer in the terminal.
+//! Walks through the choices of an [`InstallPlan`] page by page, checking them as they are typed,
+//! then installs it or writes it as a config file for `axinstall-cli config`.
+
+mod form;
+mod ui;
+
+use crate::args;
+use axinstall_core::functions::users;
+use axinstall_core::types::{
+    BaseSource, DesktopSetup, GraphicsMode, Microcode, PartitionMode, PowerDaemon, UserKit,
+};
+use axinstall_core::{
+    BaseSystem, Bootloader, InstallError, InstallPlan, Installer, Locale, Network, Partitioning,
+    Result, Settings, User,
+};
+use clap::ArgEnum;
+use form::{Form, Id};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::DefaultTerminal;
+use std::path::PathBuf;
+
+/// Runs the wizard, then does what was picked on the summary page.
+pub fn wizard() -> Result<()> {
+    // Reading the disks and hardware may log, before the terminal is taken over
+    let mut form = Form::new();
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, &mut form);
+    ratatui::restore();
+    if !result.map_err(|e| InstallError::io("Run the wizard", e))? {
+        return Err(InstallError::Cancelled);
+    }
+    let plan = plan(&form)?;
+    if form.value(Id::Action) == "write config" {
+        let path = form.value(Id::Output);
+        let config = serde_json::to_string_pretty(&plan.to_config()).unwrap();
+        std::fs::write(&path, config + "\n")
+            .map_err(|e| InstallError::io(format!("Write config {path:?}"), e))?;
+        log::info!("Config written to {path}, install it with `axinstall-cli config {path}`");
+        return Ok(());
+    }
+    Installer::new(plan).run()?;
+    log::info!("Installation finished! You may reboot now!");
+    Ok(())
+}
+
+/// The event loop, false when the wizard was quit.
+fn run(terminal: &mut DefaultTerminal, form: &mut Form) -> std::io::Result<bool> {
+    loop {
+        terminal.draw(|frame| ui::draw(frame, form))?;
+        let Event::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                return Ok(false)
+            }
+            KeyCode::Enter if form.next() => return Ok(true),
+            KeyCode::Esc => form.back(),
+            KeyCode::Tab => form.focus_next(true),
+            KeyCode::BackTab => form.focus_next(false),
+            KeyCode::Up => form.scroll(true),
+            KeyCode::Down => form.scroll(false),
+            KeyCode::Backspace => form.backspace(),
+            KeyCode::Char(c) => form.type_char(c),
+            _ => {}
+        }
+    }
+}
+
+/// ### Builds the plan from a submitted form, hashing the passwords.
+/// What the wizard doesn't ask for keeps the defaults of config files.
+fn plan(form: &Form) -> Result<InstallPlan> {
+    let words = |id: Id| -> Vec<String> {
+        form.value(id)
+            .split_whitespace()
+            .map(String::from)
+            .collect()
+    };
+    let mode = if form.value(Id::Mode) == "manual" {
+        PartitionMode::Manual
+    } else {
+        PartitionMode::Auto
+    };
+    let partitions = match mode {
+        // Already checked as they were typed
+        PartitionMode::Manual => words(Id::Partitions)
+            .iter()
+            .filter_map(|partition| args::parse_partitions(partition).ok())
+            .collect(),
+        PartitionMode::Auto => Vec::new(),
+    };
+    let mut new_users = Vec::new();
+    for user in &form.users {
+        new_users.push(User {
+            name: user.name.clone(),
+            password: users::hash_pass(&user.password)?,
+            hasroot: user.sudo,
+            shell: user.shell.clone(),
+        });
+    }
+    new_users.push(User {
+        name: form.value(Id::Username),
+        password: users::hash_pass(&form.value(Id::Password))?,
+        hasroot: form.toggle(Id::Sudo),
+        shell: form.value(Id::Shell),
+    });
+    let default_kernel = form.value(Id::DefaultKernel);
+    let kits = form
+        .field(Id::Kits)
+        .checked()
+        .iter()
+        .filter_map(|kit| match kit.as_str() {
+            "developer" => Some(UserKit::Developer),
+            "hacker" => Some(UserKit::Hacker),
+            "artist" => Some(UserKit::Artist),
+            "office" => Some(UserKit::Office),
+            "entertainment" => Some(UserKit::Entertainment),
+            _ => None,
+        })
+        .collect();
+    Ok(InstallPlan {
+        partitioning: Partitioning {
+            device: PathBuf::from(form.value(Id::Disk)),
+            mode,
+            efi: form.toggle(Id::Efi),
+            partitions,
+        },
+        base: BaseSystem {
+            source: BaseSource::Pacstrap,
+            kernels: words(Id::Kernels),
+            microcode: Microcode::Auto,
+            flatpak: form.toggle(Id::Flatpak),
+        },
+        bootloader: Bootloader {
+            efidir: PathBuf::from(form.value(Id::EfiDir)),
+            default_kernel: (!default_kernel.is_empty()).then_some(default_kernel),
+        },
+        locale: Locale {
+            locales: vec![form.value(Id::Locale)],
+            keymap: form.value(Id::Keymap),
+            timezone: form.value(Id::Timezone),
+        },
+        network: Network {
+            hostname: form.value(Id::Hostname),
+            ipv6: form.toggle(Id::Ipv6),
+        },
+        users: new_users,
+        root_password: users::hash_pass(&form.value(Id::RootPassword))?,
+        desktop: DesktopSetup::from_str(&form.value(Id::Desktop), true).ok(),
+        power_daemon: PowerDaemon::default(),
+        nvidia: form.toggle(Id::Nvidia).then_some(GraphicsMode::default()),
+        swap: form.value(Id::Swap).parse().unwrap_or(0),
+        kits,
+        extra_packages: words(Id::ExtraPackages),
+        initramfs: Default::default(),
+        settings: Settings::default(),
+    })
+}
@@ -0,0 +1,161 @@
+//! Draws the current page of the wizard.
+
+use super::form::{Field, Form, Kind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, List, ListState, Paragraph, Wrap};
+use ratatui::Frame;
+
+/// Rows shown by the list of the focused field
+const LIST_ROWS: u16 = 8;
+
+const HELP: &str =
+    "Tab/Shift-Tab: field  Up/Down: pick  Space: toggle  Enter: next  Esc: back  Ctrl-C: quit";
+
+pub fn draw(frame: &mut Frame, form: &Form) {
+    let page = &form.pages[form.page];
+    let [header, body, footer] = Layout::vertical([
+        Constraint::Length(2),
+        Constraint::Min(0),
+        Constraint::Length(1),
+    ])
+    .areas(frame.area());
+    frame.render_widget(
+        Line::from(format!(
+            "AxOS installer  [{}/{}] {}",
+            form.page + 1,
+            form.pages.len(),
+            page.title
+        ))
+        .bold(),
+        header,
+    );
+    frame.render_widget(Line::from(HELP).dim(), footer);
+
+    let body = if form.is_last_page() {
+        let summary = summary(form);
+        let [summary_area, rest] = Layout::vertical([
+            Constraint::Length(summary.len() as u16 + 1),
+            Constraint::Min(0),
+        ])
+        .areas(body);
+        frame.render_widget(
+            Paragraph::new(summary).wrap(Wrap { trim: false }),
+            summary_area,
+        );
+        rest
+    } else {
+        body
+    };
+
+    let constraints = page.fields.iter().enumerate().map(|(i, field)| {
+        if i == form.focus && field.is_list() {
+            Constraint::Length(LIST_ROWS + 2)
+        } else {
+            Constraint::Length(1)
+        }
+    });
+    let areas = Layout::vertical(constraints).split(body);
+    for (i, field) in page.fields.iter().enumerate() {
+        let focused = i == form.focus;
+        if focused && field.is_list() {
+            draw_list(frame, form, field, areas[i]);
+        } else {
+            draw_line(frame, form, field, focused, areas[i]);
+        }
+    }
+}
+
+/// Every field of the pages before the summary, with its value, and the users added before the last one.
+fn summary(form: &Form) -> Vec<Line<'static>> {
+    let mut lines: Vec<Line<'static>> = form.pages[..form.page]
+        .iter()
+        .flat_map(|page| &page.fields)
+        .map(|field| {
+            Line::from(vec![
+                Span::from(format!("{}: ", field.label)).dim(),
+                Span::from(field.display()),
+            ])
+        })
+        .collect();
+    if !form.users.is_empty() {
+        let names: Vec<&str> = form.users.iter().map(|user| user.name.as_str()).collect();
+        lines.push(Line::from(vec![
+            Span::from("Users added before: ").dim(),
+            Span::from(names.join(", ")),
+        ]));
+    }
+    lines
+}
+
+fn error_span(form: &Form, field: &Field) -> Option<Span<'static>> {
+    if !field.touched {
+        return None;
+    }
+    form.check(field.id)
+        .err()
+        .map(|error| Span::from(format!("  {error}")).red())
+}
+
+fn draw_line(frame: &mut Frame, form: &Form, field: &Field, focused: bool, area: Rect) {
+    let mut spans = vec![
+        Span::from(if focused { "> " } else { "  " }),
+        Span::from(format!("{}: ", field.label)).bold(),
+    ];
+    match &field.kind {
+        Kind::Toggle(on) => spans.push(Span::from(if *on { "[x]" } else { "[ ]" })),
+        // Free text choices and lists shown folded
+        _ => spans.push(Span::from(field.display())),
+    }
+    if focused && matches!(field.kind, Kind::Text { .. } | Kind::Choice { .. }) {
+        spans.push(Span::from("_").slow_blink());
+    }
+    spans.extend(error_span(form, field));
+    let style = if focused {
+        Style::new().yellow()
+    } else {
+        Style::new()
+    };
+    frame.render_widget(Line::from(spans).style(style), area);
+}
+
+fn draw_list(frame: &mut Frame, form: &Form, field: &Field, area: Rect) {
+    let mut title = vec![Span::from(format!(" {} ", field.label)).bold()];
+    let (items, cursor): (Vec<String>, usize) = match &field.kind {
+        Kind::Choice { filter, cursor, .. } => {
+            title.push(Span::from(format!("filter: {filter}_ ")));
+            let items = field
+                .matches()
+                .into_iter()
+                .map(|(_, label)| label.to_string())
+                .collect();
+            (items, *cursor)
+        }
+        Kind::Multi {
+            options,
+            checked,
+            cursor,
+        } => {
+            let items = options
+                .iter()
+                .zip(checked)
+                .map(|(option, checked)| {
+                    format!("[{}] {}", if *checked { "x" } else { " " }, option)
+                })
+                .collect();
+            (items, *cursor)
+        }
+        _ => (Vec::new(), 0),
+    };
+    let mut block = Block::bordered().yellow().title(Line::from(title));
+    if let Some(error) = error_span(form, field) {
+        block = block.title_bottom(Line::from(error));
+    }
+    let list = List::new(items)
+        .block(block)
+        .highlight_symbol("> ")
+        .highlight_style(Style::new().reversed());
+    let mut state = ListState::default().with_selected(Some(cursor));
+    frame.render_stateful_widget(list, area, &mut state);
+}